@@ -0,0 +1,46 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures_util::StreamExt;
+use std::io::Cursor;
+use tokio::io::BufReader;
+use tokio_rev_lines::RevLines;
+
+fn log_data(line: &str) -> Vec<u8> {
+    line.repeat(20_000).into_bytes()
+}
+
+async fn count_lines(data: &[u8]) -> usize {
+    let reader = BufReader::new(Cursor::new(data));
+    let rev_lines = RevLines::new(reader).await.unwrap();
+    rev_lines
+        .map(|line| line.unwrap().len())
+        .fold(0, |acc, len| async move { acc + len })
+        .await
+}
+
+fn decode(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let inputs = [
+        (
+            "ascii",
+            log_data("2024-01-01T00:00:00Z INFO request handled in 12ms path=/api/v1/items\n"),
+        ),
+        (
+            "non_ascii",
+            log_data("2024-01-01T00:00:00Z INFO Anfrage für Äpfel bearbeitet in 12ms\n"),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("decode");
+    for (name, data) in inputs.iter() {
+        group.throughput(Throughput::Bytes(data.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), data, |b, data| {
+            b.to_async(&runtime).iter(|| count_lines(data))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+//! Reads arbitrary bytes backwards and checks that the lines and their
+//! terminators put back together give the input byte for byte, and that the
+//! lines match `str::lines`. The first byte picks the capacity, the second
+//! the options and delimiters, the rest is the reader.
+
+#![no_main]
+
+use futures_util::StreamExt;
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use std::ops::Range;
+use tokio::io::BufReader;
+use tokio_rev_lines::{NulPolicy, RevLinesBuilder};
+
+fn builder(cap: usize, flags: u8) -> RevLinesBuilder {
+    let nul_policy = if flags & 4 != 0 {
+        NulPolicy::SplitOnNul
+    } else {
+        NulPolicy::Keep
+    };
+
+    RevLinesBuilder::new()
+        .capacity(cap)
+        .with_absolute_seeks(flags & 1 != 0)
+        .strict(flags & 2 != 0)
+        .nul_policy(nul_policy)
+        .lossy(true)
+}
+
+async fn spans(cap: usize, flags: u8, data: &[u8], ends: bool) -> Vec<(String, Range<u64>)> {
+    let rev_lines = builder(cap, flags)
+        .build(BufReader::new(Cursor::new(data)))
+        .await
+        .unwrap();
+
+    rev_lines
+        .lines_with_span(ends)
+        .map(Result::unwrap)
+        .collect()
+        .await
+}
+
+fuzz_target!(|input: &[u8]| {
+    let (cap, flags, data) = match input {
+        [cap, flags, data @ ..] => (*cap as usize, *flags, data),
+        _ => return,
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        let with_ends = spans(cap, flags, data, true).await;
+        let without_ends = spans(cap, flags, data, false).await;
+        assert_eq!(with_ends.len(), without_ends.len());
+
+        // The lines with their terminators, in forward order, are the input
+        let mut reassembled = Vec::new();
+        for (_, span) in with_ends.iter().rev() {
+            assert_eq!(span.start, reassembled.len() as u64);
+            reassembled.extend_from_slice(&data[span.start as usize..span.end as usize]);
+        }
+        assert_eq!(reassembled, data);
+
+        // Every line is its bytes without the terminator, decoded
+        for ((line, span), (_, with_end)) in without_ends.iter().zip(&with_ends) {
+            assert_eq!(span.start, with_end.start);
+            assert!(span.end <= with_end.end);
+
+            let bytes = &data[span.start as usize..span.end as usize];
+            assert_eq!(line, &String::from_utf8_lossy(bytes));
+        }
+
+        // Invalid sequences never contain a new line, so decoding the whole
+        // input at once replaces them just like decoding every line does
+        if flags & 4 == 0 {
+            let text = String::from_utf8_lossy(data);
+            let expected: Vec<&str> = text.lines().rev().collect();
+            let lines: Vec<&str> = without_ends.iter().map(|(line, _)| line.as_str()).collect();
+            assert_eq!(lines, expected);
+        }
+    });
+});
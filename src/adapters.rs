@@ -0,0 +1,1770 @@
+//! Adapters that turn a [`RevLines`] stream into a stream of other items.
+
+use crate::{Error, ParseLineError, RevLines};
+use futures_util::{future, stream, Stream, StreamExt};
+#[cfg(feature = "regex")]
+use regex::Regex;
+#[cfg(feature = "sha2")]
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::mem;
+use std::ops::Range;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{ready, Context, Poll, Waker};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncSeek, BufReader};
+
+/// Part of a chunk read that a line came from, see
+/// [`RevLines::lines_with_chunks`].
+#[cfg(feature = "debug-chunks")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineChunk {
+    /// The number of chunks read before this one.
+    pub index: u64,
+    /// The bytes of the line and its terminator within the chunk.
+    pub range: Range<usize>,
+}
+
+/// Where a line is in the reader, see [`RevLines::lines_with_position`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinePosition {
+    /// The first line of the reader, which is yielded last.
+    First,
+    /// A line between the first and the last line.
+    Middle,
+    /// The last line of the reader, which is yielded first.
+    Last,
+    /// The only line of the reader, both its first and last line.
+    Only,
+}
+
+/// The delimiter that ends a line, see [`RevLines::lines_with_delimiters`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delimiter {
+    /// A single new line, or the end of the reader.
+    Line,
+    /// A new line followed by one or more empty lines.
+    Paragraph,
+}
+
+/// An item of a stream that yields more than lines, like
+/// [`RevLines::with_heartbeats`] and [`RevLines::framed`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RevItem {
+    /// The scan starts, before the first line.
+    Start,
+    /// The next line.
+    Line(String),
+    /// A line is still being assembled. `bytes_scanned` counts the bytes
+    /// read so far from the end of the reader.
+    Heartbeat { bytes_scanned: u64 },
+    /// The scan is over, after the last line.
+    End,
+}
+
+/// An async stream of the lines with heartbeats during long lines, created
+/// by [`RevLines::with_heartbeats`].
+pub struct Heartbeats<R> {
+    rev_lines: RevLines<R>,
+}
+
+/// An async stream of every line paired with the line before it in the
+/// reader, created by [`RevLines::windows2`].
+pub struct Windows2<R> {
+    rev_lines: RevLines<R>,
+    current: Option<String>,
+    // `Some` once the line after `current` has been read ahead
+    lookahead: Option<Option<Result<String, Error>>>,
+}
+
+/// An async stream of the lines before a header of a fixed number of lines
+/// at the start of the reader, created by [`RevLines::skip_header_lines`].
+pub struct SkipHeader<R> {
+    rev_lines: RevLines<R>,
+    header_len: usize,
+    // Lines that would be the header if the start of the reader came next
+    held: VecDeque<Result<String, Error>>,
+    flushing: bool,
+}
+
+/// An async stream of the lines that resolves a [`Completion`] once it ends,
+/// created by [`RevLines::track_completion`].
+pub struct TrackCompletion<R> {
+    rev_lines: RevLines<R>,
+    state: Arc<Mutex<CompletionState>>,
+}
+
+/// A future that resolves once a [`TrackCompletion`] stream reaches the
+/// start of the reader or fails, created by [`RevLines::track_completion`].
+///
+/// Resolves to `true` if the start of the reader was reached, and to `false`
+/// if the stream yielded an error, ended early or was dropped first.
+pub struct Completion {
+    state: Arc<Mutex<CompletionState>>,
+}
+
+#[derive(Default)]
+struct CompletionState {
+    reached_start: Option<bool>,
+    waker: Option<Waker>,
+}
+
+impl CompletionState {
+    fn complete(state: &Mutex<CompletionState>, reached_start: bool) {
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.reached_start.is_none() {
+            state.reached_start = Some(reached_start);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> RevLines<R> {
+    /// Create an async stream of pages of the lines of a `BufReader<R>`. See
+    /// [`RevLines::pages`].
+    pub async fn paginate(
+        target_bytes: usize,
+        reader: BufReader<R>,
+    ) -> Result<impl Stream<Item = Result<Vec<String>, Error>>, Error> {
+        Ok(RevLines::new(reader).await?.pages(target_bytes))
+    }
+
+    /// Group the lines into pages of up to `target_bytes` bytes of line
+    /// content, not counting line terminators, for display on fixed-height
+    /// screens. A page is yielded once the next line doesn't fit on it, so
+    /// a line longer than `target_bytes` forms a page of its own.
+    pub fn pages(self, target_bytes: usize) -> impl Stream<Item = Result<Vec<String>, Error>> {
+        let state = Pages {
+            rev_lines: self,
+            target_bytes,
+            page: Vec::new(),
+            page_len: 0,
+        };
+
+        stream::unfold(state, |mut state| async {
+            state.next_page().await.map(|page| (page, state))
+        })
+    }
+
+    /// Map every line through the async function `f`, yielding its output.
+    ///
+    /// Lines are processed one after another: `f` isn't called for a line
+    /// until the future returned for the previous one has completed, so the
+    /// outputs keep the reverse order of the lines. Errors are passed through.
+    pub fn then_lines<Fut, F>(self, mut f: F) -> impl Stream<Item = Result<Fut::Output, Error>>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future,
+    {
+        self.then(move |line| {
+            let fut = line.map(&mut f);
+
+            async move {
+                match fut {
+                    Ok(fut) => Ok(fut.await),
+                    Err(e) => Err(e),
+                }
+            }
+        })
+    }
+
+    /// Map every line to a future with `f` without polling it, for queueing
+    /// or spawning the work. Unlike [`RevLines::then_lines`], the futures
+    /// may run concurrently, so the order their outputs are ready in is up
+    /// to the consumer and needn't match the order of the lines.
+    pub fn map_to_tasks<Fut, F>(self, mut f: F) -> impl Stream<Item = Result<Fut, Error>>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future,
+    {
+        self.map(move |line| line.map(&mut f))
+    }
+
+    /// Create an async stream of the lines of a `BufReader<R>` parsed with
+    /// `str::parse`. See [`RevLines::parse_lines`].
+    pub async fn new_parsed<T: FromStr>(
+        reader: BufReader<R>,
+    ) -> Result<impl Stream<Item = Result<T, ParseLineError<T::Err>>>, Error> {
+        Ok(RevLines::new(reader).await?.parse_lines())
+    }
+
+    /// Parse every line with `str::parse`, for numbers or other types with
+    /// a `FromStr` implementation. A line that doesn't parse yields
+    /// [`ParseLineError::Parse`] and the stream goes on with the next one.
+    pub fn parse_lines<T: FromStr>(self) -> impl Stream<Item = Result<T, ParseLineError<T::Err>>> {
+        self.map(|line| line?.parse().map_err(ParseLineError::Parse))
+    }
+
+    /// Create an async stream of the lines of a `BufReader<R>` as `Arc<str>`.
+    /// See [`RevLines::shared_lines`].
+    pub async fn new_shared(
+        reader: BufReader<R>,
+    ) -> Result<impl Stream<Item = Result<Arc<str>, Error>>, Error> {
+        Ok(RevLines::new(reader).await?.shared_lines())
+    }
+
+    /// Yield every line as an `Arc<str>`, for handing the same line to
+    /// several consumers or indices. Cloning an item only bumps a reference
+    /// count instead of copying the line.
+    pub fn shared_lines(self) -> impl Stream<Item = Result<Arc<str>, Error>> {
+        self.map(|line| line.map(Arc::from))
+    }
+
+    /// Split every line into its fields separated by the ASCII byte `sep`,
+    /// like `str::split`: empty fields before, between and after separators
+    /// are kept, and an empty line is a single empty field. A non-ASCII `sep`
+    /// never stands for a whole character in UTF-8, so it splits nothing.
+    pub fn split_each(self, sep: u8) -> impl Stream<Item = Result<Vec<String>, Error>> {
+        self.map(move |line| {
+            let line = line?;
+            if !sep.is_ascii() {
+                return Ok(vec![line]);
+            }
+
+            Ok(line.split(char::from(sep)).map(String::from).collect())
+        })
+    }
+
+    /// Map every line through `f`, yielding the outputs that are `Some` and
+    /// skipping lines for which it returns `None`. Errors are passed through.
+    pub fn filter_map_lines<T, F>(self, mut f: F) -> impl Stream<Item = Result<T, Error>>
+    where
+        F: FnMut(String) -> Option<T>,
+    {
+        self.filter_map(move |line| future::ready(line.map(&mut f).transpose()))
+    }
+
+    /// Leave out every line that is the same as one of the last `n` lines
+    /// yielded, which also catches repeats with other lines in between, unlike
+    /// only comparing neighbouring lines. Memory use is bounded by the `n`
+    /// lines kept for comparison, and `n` of 0 keeps every line.
+    pub fn dedup_within_window(self, n: usize) -> impl Stream<Item = Result<String, Error>> {
+        let mut recent = VecDeque::with_capacity(n);
+
+        self.filter(move |line| {
+            let keep = match line {
+                Ok(line) if n > 0 => {
+                    let keep = !recent.contains(line);
+                    if keep {
+                        if recent.len() == n {
+                            recent.pop_front();
+                        }
+                        recent.push_back(line.clone());
+                    }
+                    keep
+                }
+                _ => true,
+            };
+
+            future::ready(keep)
+        })
+    }
+
+    /// Leave out every line that was already yielded, so each distinct line
+    /// comes once, at its most recent occurrence in the reader. Every
+    /// distinct line is kept for comparison until the stream is dropped, so
+    /// memory use grows with the number and length of distinct lines; see
+    /// [`RevLines::dedup_within_window`] for a bounded alternative.
+    pub fn distinct(self) -> impl Stream<Item = Result<String, Error>> {
+        let mut seen = HashSet::new();
+
+        self.filter(move |line| {
+            let keep = match line {
+                Ok(line) => !seen.contains(line) && seen.insert(line.clone()),
+                Err(_) => true,
+            };
+
+            future::ready(keep)
+        })
+    }
+
+    /// Like [`RevLines::distinct`], but lines that only differ in leading or
+    /// trailing whitespace count as the same line, for comparing logs
+    /// written with different padding. Lines are yielded as they are, at the
+    /// first of their occurrences read.
+    pub fn distinct_trimmed(self) -> impl Stream<Item = Result<String, Error>> {
+        let mut seen = HashSet::new();
+
+        self.filter(move |line| {
+            let keep = match line {
+                Ok(line) => {
+                    let key = line.trim();
+                    !seen.contains(key) && seen.insert(key.to_string())
+                }
+                Err(_) => true,
+            };
+
+            future::ready(keep)
+        })
+    }
+
+    /// Join lines into records that start with a line matching `anchor` at
+    /// its start, like a timestamp, to recover records with embedded new
+    /// lines or corrupted parts. Lines that don't start a record are held
+    /// until the earlier line that does is read, and yielded with it, joined
+    /// by `\n`. Lines before the first record start of the reader make up a
+    /// record of their own. Errors are yielded in place, without ending the
+    /// record being joined.
+    #[cfg(feature = "regex")]
+    pub fn resync_anchor(self, anchor: Regex) -> impl Stream<Item = Result<String, Error>> {
+        fn take_record(held: &mut Vec<String>) -> String {
+            held.reverse();
+            let record = held.join("\n");
+            held.clear();
+            record
+        }
+
+        let state = (self, anchor, Vec::new());
+        stream::unfold(state, |(mut rev_lines, anchor, mut held)| async move {
+            let record = loop {
+                match rev_lines.next_line().await {
+                    Some(Ok(line)) => {
+                        let starts_record = anchor.find(&line).is_some_and(|m| m.start() == 0);
+                        held.push(line);
+                        if starts_record {
+                            break Ok(take_record(&mut held));
+                        }
+                    }
+                    Some(Err(e)) => break Err(e),
+                    None if held.is_empty() => return None,
+                    None => break Ok(take_record(&mut held)),
+                }
+            };
+
+            Some((record, (rev_lines, anchor, held)))
+        })
+    }
+
+    /// Yield the tail read so far after every line, in forward order, so a
+    /// UI can re-render the whole visible tail on each new line. Every
+    /// snapshot is the one before it with the new line prepended. The lines
+    /// are shared between snapshots, but every snapshot still copies a
+    /// pointer per line of the tail, so reading `n` lines costs `O(n²)`.
+    pub fn snapshots(self) -> impl Stream<Item = Result<Vec<Arc<str>>, Error>> {
+        let mut tail = VecDeque::new();
+
+        self.map(move |line| {
+            tail.push_front(Arc::from(line?));
+            Ok(tail.iter().cloned().collect())
+        })
+    }
+
+    /// Yield the lines as [`RevItem::Line`]s between a [`RevItem::Start`] and
+    /// a [`RevItem::End`], for state machines that want explicit markers for
+    /// the scan. The end comes once the lines do, also after an error ended
+    /// them.
+    pub fn framed(self) -> impl Stream<Item = Result<RevItem, Error>> {
+        stream::once(future::ready(Ok(RevItem::Start)))
+            .chain(self.map(|line| line.map(RevItem::Line)))
+            .chain(stream::once(future::ready(Ok(RevItem::End))))
+    }
+
+    /// Yield the lines as [`RevItem::Line`]s, and a [`RevItem::Heartbeat`]
+    /// whenever `every_bytes` bytes were read without finishing a line, so
+    /// a UI stays responsive while a very long line is assembled. An
+    /// `every_bytes` of 0 never yields heartbeats.
+    pub fn with_heartbeats(mut self, every_bytes: u64) -> Heartbeats<R> {
+        self.heartbeat_interval = every_bytes;
+        Heartbeats { rev_lines: self }
+    }
+
+    /// Split off a [`Completion`] future that resolves once the stream reaches
+    /// the start of the reader or ends on an error, so a supervising task can
+    /// wait for the read to finish while another task consumes the lines.
+    /// Errors the stream keeps going after, like lines that can't be decoded
+    /// with [`ErrorMode::Yield`](crate::ErrorMode::Yield), don't resolve it.
+    pub fn track_completion(self) -> (TrackCompletion<R>, Completion) {
+        let state = Arc::new(Mutex::new(CompletionState::default()));
+        let stream = TrackCompletion {
+            rev_lines: self,
+            state: Arc::clone(&state),
+        };
+
+        (stream, Completion { state })
+    }
+
+    /// Use the stream in `tokio_stream::StreamExt` chains. `tokio_stream`
+    /// re-exports the same `Stream` trait, so this only pins down the type
+    /// for code that doesn't depend on `futures_util` itself, and converts
+    /// with `tokio_stream::wrappers` types that expect an `Unpin` stream.
+    #[cfg(feature = "tokio-stream")]
+    pub fn to_tokio_stream(
+        self,
+    ) -> impl tokio_stream::Stream<Item = Result<String, Error>> + Unpin {
+        self
+    }
+
+    /// Leave out the first `n` lines of the reader, like the version line and
+    /// column names some logs start with. Since those lines are read last,
+    /// the `n` most recently read lines are held back until it is clear
+    /// whether they are the header.
+    ///
+    /// If the stream ends before the start of the reader is reached, for
+    /// example on an I/O error, the held back lines are yielded after all.
+    /// The held back lines count against
+    /// [`RevLinesBuilder::max_lookahead`](crate::RevLinesBuilder::max_lookahead).
+    pub fn skip_header_lines(self, n: usize) -> SkipHeader<R> {
+        let capacity = n.min(self.max_lookahead.unwrap_or(n)) + 1;
+
+        SkipHeader {
+            rev_lines: self,
+            header_len: n,
+            held: VecDeque::with_capacity(capacity),
+            flushing: false,
+        }
+    }
+
+    /// Yield every line together with the line read after it, which is the
+    /// previous line in the reader, for parsing that needs one line of
+    /// context. The first line of the reader comes paired with `None`, and
+    /// so does a line whose lookahead is an error, which is yielded right
+    /// after the pair.
+    ///
+    /// The lookahead line is buffered internally and yielded again as the
+    /// first element of the next pair.
+    pub fn windows2(self) -> Windows2<R> {
+        Windows2 {
+            rev_lines: self,
+            current: None,
+            lookahead: None,
+        }
+    }
+
+    /// Yield the lines in forward order, first line first. Reading from the
+    /// end can't produce the first line before everything after it was read,
+    /// so this holds every line of the reader in memory before yielding the
+    /// first one; use a forward reader instead where possible. Errors keep
+    /// their place between the lines. With
+    /// [`RevLinesBuilder::max_lookahead`](crate::RevLinesBuilder::max_lookahead)
+    /// set, a reader of more lines yields only the error.
+    pub fn forward_lines_buffered(mut self) -> impl Stream<Item = Result<String, Error>> {
+        let max_lookahead = self.max_lookahead;
+
+        let lines = async move {
+            let mut lines = Vec::new();
+            while let Some(line) = self.next().await {
+                if let Some(max) = max_lookahead {
+                    if lines.len() >= max {
+                        return vec![Err(Error::LookaheadExceeded { max })];
+                    }
+                }
+                lines.push(line);
+            }
+
+            lines.reverse();
+            lines
+        };
+
+        stream::once(lines).flat_map(stream::iter)
+    }
+
+    /// Yield pairs of the first and last line, the second and second to last
+    /// line, and so on, reading forwards from `forward` while reading
+    /// backwards from this stream. `forward` must read the same bytes and
+    /// start at offset 0.
+    ///
+    /// The stream ends once the two ends meet in the middle. With an odd
+    /// number of lines the middle line comes paired with itself, with an
+    /// even number of lines the last pair is the two middle lines.
+    pub fn zip_forward<F: AsyncRead + Unpin>(
+        self,
+        forward: BufReader<F>,
+    ) -> impl Stream<Item = Result<(String, String), Error>> {
+        let state = ZipForward {
+            rev_lines: self,
+            forward,
+            forward_pos: 0,
+            done: false,
+        };
+
+        stream::unfold(state, |mut state| async {
+            state.next_pair().await.map(|pair| (pair, state))
+        })
+    }
+
+    /// Yield paragraphs, the runs of lines between empty lines, last
+    /// paragraph first. A paragraph keeps the terminators between its lines
+    /// as they were, so both `\n\n` and `\r\n\r\n` separate paragraphs and
+    /// the line breaks inside a CRLF paragraph stay `\r\n`. Runs of several
+    /// empty lines don't produce empty paragraphs.
+    pub fn paragraphs(self) -> impl Stream<Item = Result<String, Error>> {
+        stream::unfold(self, |mut rev_lines| async move {
+            // The lines and terminators of the paragraph, last first
+            let mut parts = Vec::new();
+
+            loop {
+                let line = match rev_lines.next_line().await {
+                    Some(Ok(line)) => line,
+                    Some(Err(e)) => return Some((Err(e), rev_lines)),
+                    None if parts.is_empty() => return None,
+                    None => break,
+                };
+
+                if line.is_empty() {
+                    if parts.is_empty() {
+                        continue;
+                    }
+                    break;
+                }
+
+                if !parts.is_empty() {
+                    let terminator = if rev_lines.terminator_len == 2 {
+                        "\r\n"
+                    } else {
+                        "\n"
+                    };
+                    parts.push(terminator.to_string());
+                }
+                parts.push(line);
+            }
+
+            parts.reverse();
+            Some((Ok(parts.concat()), rev_lines))
+        })
+    }
+
+    /// Yield the non-empty lines together with the delimiter that ends them,
+    /// for telling line breaks from paragraph breaks in one pass. Where a
+    /// paragraph delimiter `\n\n` matches, it takes precedence over the two
+    /// line delimiters it is made of, so the empty line in between is never
+    /// yielded as a line of its own. More empty lines after it extend the
+    /// same paragraph delimiter. Empty lines at the start of the reader are
+    /// left out, and CRLF terminators count like `\n`.
+    pub fn lines_with_delimiters(self) -> impl Stream<Item = Result<(String, Delimiter), Error>> {
+        let mut delimiter = Delimiter::Line;
+
+        self.filter_map(move |line| {
+            let item = match line {
+                Ok(line) if line.is_empty() => {
+                    delimiter = Delimiter::Paragraph;
+                    None
+                }
+                Ok(line) => Some(Ok((line, mem::replace(&mut delimiter, Delimiter::Line)))),
+                Err(e) => Some(Err(e)),
+            };
+
+            future::ready(item)
+        })
+    }
+
+    /// Yield every line together with whether it is the first or last line
+    /// of the reader, for styling those lines differently. The first line
+    /// yielded is the last line, and the line starting at offset 0, or where
+    /// [`RevLinesBuilder::stop_at`](crate::RevLinesBuilder::stop_at) stops
+    /// reading, is the first line. Errors yielded before the first line don't
+    /// count as the last line.
+    pub fn lines_with_position(self) -> impl Stream<Item = Result<(String, LinePosition), Error>> {
+        stream::unfold((self, true), |(mut rev_lines, is_last)| async move {
+            let line = rev_lines.next_line().await?;
+            let is_first = rev_lines.line_start == rev_lines.stop_pos;
+            let still_last = is_last && line.is_err();
+
+            let position = match (is_first, is_last) {
+                (true, true) => LinePosition::Only,
+                (true, false) => LinePosition::First,
+                (false, true) => LinePosition::Last,
+                (false, false) => LinePosition::Middle,
+            };
+
+            Some((line.map(|line| (line, position)), (rev_lines, still_last)))
+        })
+    }
+
+    /// Yield every line together with the range of its bytes in the reader,
+    /// for highlighting the line in its source. The range starts after the
+    /// previous line's terminator and ends before the line's own terminator,
+    /// or after it if `include_terminator` is set.
+    ///
+    /// The range covers the line's bytes as read, before any processing like
+    /// case folding changes them.
+    pub fn lines_with_span(
+        self,
+        include_terminator: bool,
+    ) -> impl Stream<Item = Result<(String, Range<u64>), Error>> {
+        stream::unfold(self, move |mut rev_lines| async move {
+            let line = rev_lines.next_line().await?;
+            let span = rev_lines.line_span(include_terminator);
+
+            Some((line.map(|line| (line, span)), rev_lines))
+        })
+    }
+
+    /// Yield the bytes of every line together with the line decoded from
+    /// them, for consumers that hash or round-trip the exact bytes while
+    /// showing the text. The bytes are the line without its terminator, so
+    /// unlike re-encoding the string they keep invalid sequences that lossy
+    /// decoding replaced. Unlike the spans of
+    /// [`RevLines::lines_with_span`], they come after the options that work
+    /// on bytes, like ASCII case folding, NUL handling and escaped
+    /// delimiters, but before the options that work on the decoded string.
+    /// Lines that can't be decoded are yielded as errors.
+    pub fn lines_with_bytes(self) -> impl Stream<Item = Result<(Vec<u8>, String), Error>> {
+        stream::unfold(self, |mut rev_lines| async move {
+            let line = future::poll_fn(|cx| {
+                rev_lines.poll_next_decoded(cx, |rev_lines| {
+                    let line = rev_lines.decode_line_copy()?;
+                    Ok((std::mem::take(&mut rev_lines.line), line))
+                })
+            })
+            .await?;
+
+            Some((line, rev_lines))
+        })
+    }
+
+    /// Yield every line together with the chunk reads its bytes and
+    /// terminator came from, for debugging how the reader is split into
+    /// lines. Chunks are numbered in the order they are read, starting with
+    /// the chunk at the end of the reader.
+    #[cfg(feature = "debug-chunks")]
+    pub fn lines_with_chunks(self) -> impl Stream<Item = Result<(String, Vec<LineChunk>), Error>> {
+        stream::unfold(self, |mut rev_lines| async move {
+            let line = rev_lines.next_line().await?;
+            let chunks = rev_lines.line_chunks();
+
+            Some((line.map(|line| (line, chunks)), rev_lines))
+        })
+    }
+
+    /// Yield every line together with the SHA-256 digest of its bytes. The
+    /// digest covers the line's content only, without the line terminator.
+    #[cfg(feature = "sha2")]
+    pub fn lines_with_digest(self) -> impl Stream<Item = Result<(String, [u8; 32]), Error>> {
+        self.map(|line| {
+            line.map(|line| {
+                let digest = Sha256::digest(line.as_bytes()).into();
+                (line, digest)
+            })
+        })
+    }
+}
+
+struct Pages<R> {
+    rev_lines: RevLines<R>,
+    target_bytes: usize,
+    page: Vec<String>,
+    page_len: usize,
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> Pages<R> {
+    async fn next_page(&mut self) -> Option<Result<Vec<String>, Error>> {
+        loop {
+            if self.page_len >= self.target_bytes && !self.page.is_empty() {
+                return Some(Ok(self.take_page(Vec::new(), 0)));
+            }
+
+            let line = match self.rev_lines.next_line().await {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Some(Err(e)),
+                None if self.page.is_empty() => return None,
+                None => return Some(Ok(self.take_page(Vec::new(), 0))),
+            };
+
+            if self.page_len + line.len() > self.target_bytes && !self.page.is_empty() {
+                let len = line.len();
+                return Some(Ok(self.take_page(vec![line], len)));
+            }
+
+            self.page_len += line.len();
+            self.page.push(line);
+        }
+    }
+
+    fn take_page(&mut self, next_page: Vec<String>, next_page_len: usize) -> Vec<String> {
+        self.page_len = next_page_len;
+        mem::replace(&mut self.page, next_page)
+    }
+}
+
+struct ZipForward<R, F> {
+    rev_lines: RevLines<R>,
+    forward: BufReader<F>,
+    forward_pos: u64,
+    done: bool,
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin, F: AsyncRead + Unpin> ZipForward<R, F> {
+    async fn next_pair(&mut self) -> Option<Result<(String, String), Error>> {
+        if self.done {
+            return None;
+        }
+
+        let forward_start = self.forward_pos;
+        let mut forward_line = Vec::new();
+        let size = match self.forward.read_until(b'\n', &mut forward_line).await {
+            Ok(0) => return None,
+            Ok(size) => size,
+            Err(e) => return self.fail(Error::Io(e)),
+        };
+        self.forward_pos += size as u64;
+
+        if forward_line.last() == Some(&b'\n') {
+            forward_line.pop();
+            if forward_line.last() == Some(&b'\r') {
+                forward_line.pop();
+            }
+        }
+
+        let forward_line = match String::from_utf8(forward_line) {
+            Ok(line) => line,
+            Err(e) => return self.fail(Error::NotUtf8(e)),
+        };
+
+        let rev_line = match self.rev_lines.next_line().await? {
+            Ok(line) => line,
+            Err(e) => return self.fail(e),
+        };
+
+        // The cursors have passed each other
+        if forward_start > self.rev_lines.line_start {
+            return None;
+        }
+
+        // Both cursors are on the middle line
+        if forward_start == self.rev_lines.line_start {
+            self.done = true;
+        }
+
+        Some(Ok((forward_line, rev_line)))
+    }
+
+    fn fail(&mut self, e: Error) -> Option<Result<(String, String), Error>> {
+        self.done = true;
+        Some(Err(e))
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> Stream for Windows2<R> {
+    type Item = Result<(String, Option<String>), Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.current.is_none() {
+            let line = match this.lookahead.take() {
+                Some(line) => line,
+                None => ready!(Pin::new(&mut this.rev_lines).poll_next(cx)),
+            };
+
+            match line {
+                Some(Ok(line)) => this.current = Some(line),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+
+        let next = ready!(Pin::new(&mut this.rev_lines).poll_next(cx));
+        let next_line = match &next {
+            Some(Ok(line)) => Some(line.clone()),
+            Some(Err(_)) | None => None,
+        };
+        this.lookahead = Some(next);
+
+        let line = this.current.take().unwrap_or_default();
+        Poll::Ready(Some(Ok((line, next_line))))
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> Stream for SkipHeader<R> {
+    type Item = Result<String, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        while !this.flushing {
+            match ready!(Pin::new(&mut this.rev_lines).poll_next(cx)) {
+                Some(line) => {
+                    this.held.push_back(line);
+                    if this.held.len() > this.header_len {
+                        return Poll::Ready(this.held.pop_front());
+                    }
+                    if let Some(max) = this.rev_lines.max_lookahead {
+                        if this.held.len() > max {
+                            this.held.clear();
+                            this.flushing = true;
+                            return Poll::Ready(Some(Err(Error::LookaheadExceeded { max })));
+                        }
+                    }
+                }
+                None => {
+                    if this.rev_lines.reached_start() {
+                        this.held.clear();
+                    }
+                    this.flushing = true;
+                }
+            }
+        }
+
+        Poll::Ready(this.held.pop_front())
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> Stream for Heartbeats<R> {
+    type Item = Result<RevItem, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let rev_lines = &mut self.get_mut().rev_lines;
+
+        let line = match Pin::new(&mut *rev_lines).poll_next(cx) {
+            Poll::Ready(line) => line,
+            Poll::Pending => {
+                return match rev_lines.heartbeat.take() {
+                    Some(bytes_scanned) => {
+                        Poll::Ready(Some(Ok(RevItem::Heartbeat { bytes_scanned })))
+                    }
+                    None => Poll::Pending,
+                };
+            }
+        };
+
+        Poll::Ready(line.map(|line| line.map(RevItem::Line)))
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> Stream for TrackCompletion<R> {
+    type Item = Result<String, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        let line = ready!(Pin::new(&mut this.rev_lines).poll_next(cx));
+        match &line {
+            Some(Ok(_)) => {}
+            Some(Err(_)) if this.rev_lines.done => CompletionState::complete(&this.state, false),
+            Some(Err(_)) => {}
+            None => CompletionState::complete(&this.state, this.rev_lines.reached_start()),
+        }
+
+        Poll::Ready(line)
+    }
+}
+
+impl<R> Drop for TrackCompletion<R> {
+    fn drop(&mut self) {
+        CompletionState::complete(&self.state, false);
+    }
+}
+
+impl Future for Completion {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match state.reached_start {
+            Some(reached_start) => Poll::Ready(reached_start),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use crate::{ErrorMode, RevLinesBuilder};
+    use std::io::Cursor;
+    use tokio::fs::File;
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn it_maps_lines_through_an_async_function() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+
+        let lengths: Vec<_> = rev_lines
+            .then_lines(|line| async move {
+                tokio::task::yield_now().await;
+                line.len()
+            })
+            .map(|len| len.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(lengths, vec![6, 9, 5, 6]);
+    }
+
+    #[tokio::test]
+    async fn it_dedups_lines_within_a_window() {
+        async fn dedup(n: usize) -> Vec<String> {
+            let reader = BufReader::new(Cursor::new(b"a\nb\na\nc\nd\nb\na\na\n".to_vec()));
+            let rev_lines = RevLines::new(reader).await.unwrap();
+
+            rev_lines
+                .dedup_within_window(n)
+                .map(|line| line.unwrap())
+                .collect()
+                .await
+        }
+
+        assert_eq!(dedup(0).await, vec!["a", "a", "b", "d", "c", "a", "b", "a"]);
+        assert_eq!(dedup(1).await, vec!["a", "b", "d", "c", "a", "b", "a"]);
+        // a and b fall out of a window of 3 before they come again after c
+        assert_eq!(dedup(3).await, vec!["a", "b", "d", "c", "a", "b"]);
+        assert_eq!(dedup(4).await, vec!["a", "b", "d", "c"]);
+    }
+
+    #[tokio::test]
+    async fn it_yields_distinct_lines() {
+        let file = File::open("tests/repeated_line_file").await.unwrap();
+        let contents = tokio::fs::read_to_string("tests/repeated_line_file")
+            .await
+            .unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let lines: Vec<_> = rev_lines
+            .distinct()
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        let mut expected = Vec::new();
+        for line in contents.lines().rev() {
+            if !expected.contains(&line) {
+                expected.push(line);
+            }
+        }
+        assert_eq!(lines, expected);
+
+        let reader = BufReader::new(Cursor::new(b"a\nb\nc\na\nb\nb\n".to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let lines: Vec<_> = rev_lines
+            .distinct()
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(lines, vec!["b", "a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn it_yields_lines_distinct_up_to_surrounding_whitespace() {
+        let data = b"error  \nok\n  error\nok \nerror\n\twarn\n".to_vec();
+        let rev_lines = RevLines::with_capacity(3, BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .distinct_trimmed()
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(lines, vec!["\twarn", "error", "ok "]);
+    }
+
+    #[cfg(feature = "regex")]
+    #[tokio::test]
+    async fn it_resyncs_records_on_an_anchor() {
+        let file = File::open("tests/multi_line_record_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(7, BufReader::new(file))
+            .await
+            .unwrap();
+        let anchor = Regex::new(r"\d{2}:\d{2}:\d{2} ").unwrap();
+        let records: Vec<_> = rev_lines
+            .resync_anchor(anchor)
+            .map(|record| record.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(
+            records,
+            vec![
+                "12:00:03 INFO done",
+                "12:00:02 ERROR request failed:\n  body was\ncut 12:00:01 here",
+                "12:00:01 INFO started",
+                "garbled tail of an older record",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_yields_growing_snapshots_of_the_tail() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(5, BufReader::new(file))
+            .await
+            .unwrap();
+        let snapshots: Vec<_> = rev_lines
+            .snapshots()
+            .map(|snapshot| snapshot.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(snapshots.len(), 4);
+        assert_eq!(snapshots[0], vec![Arc::from("UVWXYZ")]);
+        for pair in snapshots.windows(2) {
+            assert_eq!(pair[1][1..], pair[0][..]);
+        }
+        assert_eq!(
+            snapshots[3],
+            ["ABCDEF", "GHIJK", "LMNOPQRST", "UVWXYZ"]
+                .iter()
+                .map(|line| Arc::from(*line))
+                .collect::<Vec<Arc<str>>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_yields_heartbeats_during_long_lines() {
+        let data = format!("short\n{}\nend\n", "x".repeat(100));
+        let reader = BufReader::new(Cursor::new(data.into_bytes()));
+        let rev_lines = RevLines::with_capacity(10, reader).await.unwrap();
+        let items: Vec<_> = rev_lines
+            .with_heartbeats(30)
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+
+        let lines: Vec<_> = items
+            .iter()
+            .filter_map(|item| match item {
+                RevItem::Line(line) => Some(line.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            vec!["end".to_string(), "x".repeat(100), "short".into()]
+        );
+
+        // Heartbeats only come while the long line is assembled
+        assert_eq!(items[0], RevItem::Line("end".into()));
+        let long_line = items
+            .iter()
+            .position(|item| *item == RevItem::Line("x".repeat(100)))
+            .unwrap();
+        let scanned: Vec<_> = items[1..long_line]
+            .iter()
+            .map(|item| match item {
+                RevItem::Heartbeat { bytes_scanned } => *bytes_scanned,
+                item => panic!("unexpected item {:?}", item),
+            })
+            .collect();
+        assert_eq!(scanned, vec![40, 70, 100]);
+
+        let reader = BufReader::new(Cursor::new(b"ABC\nDEF\n".to_vec()));
+        let rev_lines = RevLines::with_capacity(1, reader).await.unwrap();
+        let items: Vec<_> = rev_lines.with_heartbeats(0).collect().await;
+        assert_eq!(items.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_frames_the_lines_with_start_and_end() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(5, BufReader::new(file))
+            .await
+            .unwrap();
+        let items: Vec<_> = rev_lines.framed().map(|item| item.unwrap()).collect().await;
+
+        assert_eq!(
+            items,
+            vec![
+                RevItem::Start,
+                RevItem::Line("UVWXYZ".into()),
+                RevItem::Line("LMNOPQRST".into()),
+                RevItem::Line("GHIJK".into()),
+                RevItem::Line("ABCDEF".into()),
+                RevItem::End,
+            ]
+        );
+
+        let file = File::open("tests/empty_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let items: Vec<_> = rev_lines.framed().map(Result::unwrap).collect().await;
+        assert_eq!(items, vec![RevItem::Start, RevItem::End]);
+    }
+
+    #[tokio::test]
+    async fn it_resolves_the_completion_future() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let (lines, completion) = rev_lines.track_completion();
+
+        let supervisor = tokio::spawn(completion);
+        let consumer = tokio::spawn(async move { lines.collect::<Vec<_>>().await });
+
+        assert_eq!(consumer.await.unwrap().len(), 4);
+        assert!(supervisor.await.unwrap());
+
+        let reader = crate::tests::MockReader {
+            failing_reads: vec![std::io::ErrorKind::BrokenPipe],
+            ..crate::tests::MockReader::new(b"ABC\nDEF\n")
+        };
+        let rev_lines = RevLines::new(BufReader::new(reader)).await.unwrap();
+        let (mut lines, completion) = rev_lines.track_completion();
+
+        assert!(lines.next().await.unwrap().is_err());
+        assert!(!completion.await);
+
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let (mut lines, completion) = rev_lines.track_completion();
+
+        lines.next().await.unwrap().unwrap();
+        drop(lines);
+        assert!(!completion.await);
+
+        // The stream keeps going after a line that can't be decoded
+        let reader = BufReader::new(Cursor::new(b"ABC\n\xff\nDEF\n".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .build(reader)
+            .await
+            .unwrap();
+        let (lines, completion) = rev_lines.track_completion();
+
+        let lines: Vec<_> = lines.collect().await;
+        assert!(matches!(lines[1], Err(Error::NotUtf8(_))));
+        assert_eq!(lines.len(), 3);
+        assert!(completion.await);
+    }
+
+    #[tokio::test]
+    async fn it_paginates_lines() {
+        let file = File::open("tests/long_line_file").await.unwrap();
+        let contents = tokio::fs::read_to_string("tests/long_line_file")
+            .await
+            .unwrap();
+        let pages: Vec<_> = RevLines::paginate(12, BufReader::new(file))
+            .await
+            .unwrap()
+            .map(|page| page.unwrap())
+            .collect()
+            .await;
+
+        for page in &pages {
+            let page_len: usize = page.iter().map(String::len).sum();
+            assert!(page_len <= 12 || page.len() == 1);
+        }
+
+        let lines: Vec<_> = pages.concat();
+        let mut expected: Vec<_> = contents.lines().collect();
+        expected.reverse();
+        assert_eq!(lines, expected);
+
+        let reader = BufReader::new(Cursor::new(b"ABC\nDEFGH\nIJ\nKLMNOPQRSTU\nVW\n".to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let pages: Vec<_> = rev_lines.pages(6).map(|page| page.unwrap()).collect().await;
+
+        assert_eq!(
+            pages,
+            vec![
+                vec!["VW"],
+                vec!["KLMNOPQRSTU"],
+                vec!["IJ"],
+                vec!["DEFGH"],
+                vec!["ABC"],
+            ]
+        );
+
+        let reader = BufReader::new(Cursor::new(b"ABC\nDEF\nGH\nIJ\nK\n".to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let pages: Vec<_> = rev_lines.pages(6).map(|page| page.unwrap()).collect().await;
+
+        assert_eq!(pages, vec![vec!["K", "IJ", "GH"], vec!["DEF", "ABC"]]);
+    }
+
+    #[tokio::test]
+    async fn it_yields_shared_lines() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let shared: Vec<_> = RevLines::new_shared(BufReader::new(file))
+            .await
+            .unwrap()
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let lines: Vec<_> = rev_lines.map(|line| line.unwrap()).collect().await;
+
+        assert_eq!(shared.len(), lines.len());
+        for (shared, line) in shared.iter().zip(&lines) {
+            assert_eq!(&**shared, line.as_str());
+
+            let clone = Arc::clone(shared);
+            assert!(std::ptr::eq(clone.as_ptr(), shared.as_ptr()));
+            assert_eq!(Arc::strong_count(shared), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_maps_lines_to_unpolled_tasks() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+
+        let tasks: Vec<_> = rev_lines
+            .map_to_tasks(|line| async move { line.to_lowercase() })
+            .map(|task| tokio::spawn(task.unwrap()))
+            .collect()
+            .await;
+
+        let mut lines: Vec<_> = future::join_all(tasks)
+            .await
+            .into_iter()
+            .map(|line| line.unwrap())
+            .collect();
+        lines.sort();
+
+        assert_eq!(lines, vec!["abcdef", "ghijk", "lmnopqrst", "uvwxyz"]);
+    }
+
+    #[tokio::test]
+    async fn it_splits_paragraphs() {
+        for cap in [1, 2, 3, 4, 4096].iter() {
+            let file = File::open("tests/crlf_paragraph_file").await.unwrap();
+            let rev_lines = RevLines::with_capacity(*cap, BufReader::new(file))
+                .await
+                .unwrap();
+            let paragraphs: Vec<_> = rev_lines
+                .paragraphs()
+                .map(|paragraph| paragraph.unwrap())
+                .collect()
+                .await;
+
+            assert_eq!(
+                paragraphs,
+                vec!["bye", "hello\r\nworld", "From: a\r\nTo: b"]
+            );
+        }
+
+        let reader = BufReader::new(Cursor::new(b"\n\nA\nB\n\nC\r\nD\n\n".to_vec()));
+        let rev_lines = RevLines::with_capacity(3, reader).await.unwrap();
+        let paragraphs: Vec<_> = rev_lines
+            .paragraphs()
+            .map(|paragraph| paragraph.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(paragraphs, vec!["C\r\nD", "A\nB"]);
+    }
+
+    #[tokio::test]
+    async fn it_prefers_paragraph_delimiters_over_line_delimiters() {
+        let data = b"\n\nintro\n\np1 a\r\np1 b\r\n\r\n\n\np2\n".to_vec();
+        for cap in [1, 3, 4096].iter() {
+            let reader = BufReader::new(Cursor::new(data.clone()));
+            let rev_lines = RevLines::with_capacity(*cap, reader).await.unwrap();
+            let lines: Vec<_> = rev_lines
+                .lines_with_delimiters()
+                .map(Result::unwrap)
+                .collect()
+                .await;
+
+            assert_eq!(
+                lines,
+                vec![
+                    ("p2".to_string(), Delimiter::Line),
+                    ("p1 b".to_string(), Delimiter::Paragraph),
+                    ("p1 a".to_string(), Delimiter::Line),
+                    ("intro".to_string(), Delimiter::Paragraph),
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn it_yields_the_position_of_lines() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(4, BufReader::new(file))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .lines_with_position()
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(
+            lines,
+            vec![
+                ("UVWXYZ".to_string(), LinePosition::Last),
+                ("LMNOPQRST".to_string(), LinePosition::Middle),
+                ("GHIJK".to_string(), LinePosition::Middle),
+                ("ABCDEF".to_string(), LinePosition::First),
+            ]
+        );
+
+        let file = File::open("tests/one_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let lines: Vec<_> = rev_lines.lines_with_position().collect().await;
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].as_ref().unwrap().1, LinePosition::Only);
+
+        // A line that can't be decoded isn't the last line
+        let reader = BufReader::new(Cursor::new(b"a\nb\n\xff\n".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.lines_with_position().collect().await;
+
+        assert!(matches!(lines[0], Err(Error::NotUtf8(_))));
+        assert_eq!(lines[1].as_ref().unwrap().1, LinePosition::Last);
+        assert_eq!(lines[2].as_ref().unwrap().1, LinePosition::First);
+
+        // The line reading stops at is the first line
+        for (stop, positions) in [
+            (13, vec![LinePosition::Last, LinePosition::First]),
+            (23, vec![LinePosition::Only]),
+        ]
+        .iter()
+        {
+            let file = File::open("tests/multi_line_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(4)
+                .stop_at(*stop)
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            let lines: Vec<_> = rev_lines
+                .lines_with_position()
+                .map(|line| line.unwrap().1)
+                .collect()
+                .await;
+
+            assert_eq!(&lines, positions);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_splits_each_line_like_str_split() {
+        let data = "a=1;b=2\n;a=1;;b=2;\n\n;\nno separator\n";
+        let reader = BufReader::new(Cursor::new(data.as_bytes().to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let fields: Vec<_> = rev_lines
+            .split_each(b';')
+            .map(|fields| fields.unwrap())
+            .collect()
+            .await;
+
+        let expected: Vec<Vec<_>> = data
+            .lines()
+            .rev()
+            .map(|line| line.split(';').collect())
+            .collect();
+        assert_eq!(fields, expected);
+        assert_eq!(fields[1], vec!["", ""]);
+        assert_eq!(fields[3], vec!["", "a=1", "", "b=2", ""]);
+
+        let reader = BufReader::new(Cursor::new("caf\u{e9}\n".as_bytes().to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let fields: Vec<_> = rev_lines.split_each(0xc3).collect().await;
+        assert_eq!(fields[0].as_ref().unwrap(), &vec!["caf\u{e9}"]);
+    }
+
+    #[tokio::test]
+    async fn it_yields_buffered_lines_in_forward_order() {
+        let contents = tokio::fs::read_to_string("tests/multi_line_file")
+            .await
+            .unwrap();
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(3, BufReader::new(file))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .forward_lines_buffered()
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(lines, contents.lines().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn it_bounds_the_lines_held_for_lookahead() {
+        let build = |max_lookahead| async move {
+            let file = File::open("tests/header_file").await.unwrap();
+            RevLinesBuilder::new()
+                .capacity(4)
+                .max_lookahead(max_lookahead)
+                .build(BufReader::new(file))
+                .await
+                .unwrap()
+        };
+
+        let lines: Vec<_> = build(3).await.forward_lines_buffered().collect().await;
+        assert_eq!(lines.len(), 1);
+        assert!(matches!(lines[0], Err(Error::LookaheadExceeded { max: 3 })));
+
+        let lines = build(5).await.forward_lines_buffered().count().await;
+        assert_eq!(lines, 5);
+
+        let lines: Vec<_> = build(1).await.skip_header_lines(2).collect().await;
+        assert_eq!(lines.len(), 1);
+        assert!(matches!(lines[0], Err(Error::LookaheadExceeded { max: 1 })));
+
+        let lines = build(2).await.skip_header_lines(2).count().await;
+        assert_eq!(lines, 3);
+    }
+
+    #[tokio::test]
+    async fn it_parses_lines() {
+        let file = File::open("tests/number_file").await.unwrap();
+        let numbers: Vec<_> = RevLines::new_parsed::<i64>(BufReader::new(file))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(numbers.len(), 5);
+        assert_eq!(*numbers[0].as_ref().unwrap(), 9_007_199_254_740_993);
+        assert_eq!(*numbers[1].as_ref().unwrap(), -42);
+        assert!(matches!(numbers[2], Err(ParseLineError::Parse(_))));
+        assert_eq!(*numbers[3].as_ref().unwrap(), 0);
+        assert_eq!(*numbers[4].as_ref().unwrap(), 17);
+    }
+
+    #[tokio::test]
+    async fn it_filters_and_maps_lines() {
+        let reader = BufReader::new(Cursor::new(b"12\nabc\n\n-7\n\xff\n3x\n40\n".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .capacity(3)
+            .build(reader)
+            .await
+            .unwrap();
+
+        let numbers: Vec<_> = rev_lines
+            .filter_map_lines(|line| line.parse::<i32>().ok())
+            .collect()
+            .await;
+
+        assert_eq!(numbers.len(), 4);
+        assert_eq!(*numbers[0].as_ref().unwrap(), 40);
+        assert!(matches!(numbers[1], Err(Error::NotUtf8(_))));
+        assert_eq!(*numbers[2].as_ref().unwrap(), -7);
+        assert_eq!(*numbers[3].as_ref().unwrap(), 12);
+    }
+
+    #[tokio::test]
+    async fn it_skips_the_header_lines() {
+        for cap in [1, 7, 4096].iter() {
+            let file = File::open("tests/header_file").await.unwrap();
+            let rev_lines = RevLines::with_capacity(*cap, BufReader::new(file))
+                .await
+                .unwrap();
+
+            let lines: Vec<_> = rev_lines
+                .skip_header_lines(2)
+                .map(Result::unwrap)
+                .collect()
+                .await;
+
+            assert_eq!(lines, vec!["3,INFO,done", "2,WARN,slow", "1,INFO,started"]);
+        }
+
+        // A header longer than the reader leaves nothing
+        let file = File::open("tests/header_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        assert_eq!(rev_lines.skip_header_lines(10).count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn it_pairs_lines_with_their_bytes() {
+        let reader = BufReader::new(Cursor::new(b"ok\r\n\xffab\xc3\n".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .capacity(2)
+            .with_lossy_replacement('\u{fffd}')
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .lines_with_bytes()
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(
+            lines,
+            vec![
+                (b"\xffab\xc3".to_vec(), "\u{fffd}ab\u{fffd}".to_string()),
+                (b"ok".to_vec(), "ok".to_string()),
+            ]
+        );
+
+        // ASCII case folding works on the bytes, Unicode case folding doesn't
+        for (unicode_case, bytes) in [(false, "straße ok"), (true, "Straße OK")].iter() {
+            let reader = BufReader::new(Cursor::new("Straße OK\n"));
+            let rev_lines = RevLinesBuilder::new()
+                .to_lowercase(true)
+                .unicode_case(*unicode_case)
+                .build(reader)
+                .await
+                .unwrap();
+            let lines: Vec<_> = rev_lines
+                .lines_with_bytes()
+                .map(|line| line.unwrap())
+                .collect()
+                .await;
+            assert_eq!(
+                lines,
+                vec![(bytes.as_bytes().to_vec(), "straße ok".to_string())]
+            );
+        }
+
+        let reader = BufReader::new(Cursor::new(b"ok\n\xff\n".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.lines_with_bytes().collect().await;
+        assert!(matches!(lines[0], Err(Error::NotUtf8(_))));
+        assert_eq!(lines[1].as_ref().unwrap().0, b"ok");
+    }
+
+    #[tokio::test]
+    async fn it_pairs_lines_with_their_bytes_in_every_error_mode() {
+        let data = b"ok\n\xff\nfine\n\xfe\n";
+
+        let reader = BufReader::new(Cursor::new(&data[..]));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Collect)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .lines_with_bytes()
+            .map(|line| line.unwrap().1)
+            .collect()
+            .await;
+        assert_eq!(lines, vec!["fine", "ok"]);
+
+        let reader = BufReader::new(Cursor::new(&data[..]));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let lines: Vec<_> = rev_lines.lines_with_bytes().collect().await;
+        assert_eq!(lines.len(), 1);
+        assert!(matches!(lines[0], Err(Error::NotUtf8(_))));
+    }
+
+    #[tokio::test]
+    async fn it_pairs_lines_with_their_span() {
+        let data = b"ABCDEF\r\n\nGHIJK\nLMN\r\nUVWXYZ";
+
+        for cap in [1, 4, 4096].iter() {
+            let reader = BufReader::new(Cursor::new(&data[..]));
+            let rev_lines = RevLines::with_capacity(*cap, reader).await.unwrap();
+            let mut spans: Vec<_> = rev_lines
+                .lines_with_span(false)
+                .map(Result::unwrap)
+                .collect()
+                .await;
+            spans.reverse();
+
+            // Everything between the spans is a terminator
+            let mut pos = 0;
+            for (line, span) in spans.iter() {
+                assert_eq!(
+                    &data[span.start as usize..span.end as usize],
+                    line.as_bytes()
+                );
+                let gap = &data[pos..span.start as usize];
+                assert!(gap == b"" || gap == b"\n" || gap == b"\r\n", "{:?}", gap);
+                pos = span.end as usize;
+            }
+            assert_eq!(pos, data.len());
+
+            let reader = BufReader::new(Cursor::new(&data[..]));
+            let rev_lines = RevLines::with_capacity(*cap, reader).await.unwrap();
+            let spans: Vec<_> = rev_lines
+                .lines_with_span(true)
+                .map(|line| line.unwrap().1)
+                .collect()
+                .await;
+
+            // The spans with terminators are contiguous and cover the reader
+            assert_eq!(spans, vec![20..26, 15..20, 9..15, 8..9, 0..8]);
+        }
+
+        let reader = BufReader::new(Cursor::new(&b"a\n"[..]));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let spans: Vec<_> = rev_lines.lines_with_span(true).collect().await;
+        assert_eq!(spans[0].as_ref().unwrap(), &("a".to_string(), 0..2));
+    }
+
+    #[cfg(feature = "debug-chunks")]
+    #[tokio::test]
+    async fn it_reports_the_chunks_of_every_line() {
+        fn chunk(index: u64, range: Range<usize>) -> LineChunk {
+            LineChunk { index, range }
+        }
+
+        // 30 bytes in chunks 0: 22..30, 1: 14..22, 2: 6..14, 3: 0..6
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(8, BufReader::new(file))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .lines_with_chunks()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let expected = vec![
+            ("UVWXYZ", vec![chunk(0, 1..8)]),
+            (
+                "LMNOPQRST",
+                vec![chunk(2, 7..8), chunk(1, 0..8), chunk(0, 0..1)],
+            ),
+            ("GHIJK", vec![chunk(2, 1..7)]),
+            ("ABCDEF", vec![chunk(3, 0..6), chunk(2, 0..1)]),
+        ];
+        assert_eq!(lines.len(), expected.len());
+        for ((line, chunks), (expected_line, expected_chunks)) in lines.iter().zip(expected) {
+            assert_eq!(line, expected_line);
+            assert_eq!(chunks, &expected_chunks);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_pairs_lines_with_the_previous_line() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(5, BufReader::new(file))
+            .await
+            .unwrap();
+
+        let windows: Vec<_> = rev_lines.windows2().map(|w| w.unwrap()).collect().await;
+        let expected = vec![
+            ("UVWXYZ", Some("LMNOPQRST")),
+            ("LMNOPQRST", Some("GHIJK")),
+            ("GHIJK", Some("ABCDEF")),
+            ("ABCDEF", None),
+        ];
+
+        assert_eq!(windows.len(), expected.len());
+        for ((line, next), (expected_line, expected_next)) in windows.iter().zip(expected) {
+            assert_eq!(line, expected_line);
+            assert_eq!(next.as_deref(), expected_next);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_yields_errors_after_the_window_before_them() {
+        let reader = BufReader::new(Cursor::new(b"\xff\nabc\n".to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+
+        let windows: Vec<_> = rev_lines.windows2().collect().await;
+
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].as_ref().unwrap(), &("abc".to_string(), None));
+        assert!(matches!(windows[1], Err(Error::NotUtf8(_))));
+    }
+
+    async fn zip_forward(data: &'static str) -> Vec<(String, String)> {
+        let forward = BufReader::new(Cursor::new(data));
+        let reader = BufReader::new(Cursor::new(data));
+        let rev_lines = RevLines::with_capacity(3, reader).await.unwrap();
+
+        rev_lines
+            .zip_forward(forward)
+            .map(|pair| pair.unwrap())
+            .collect()
+            .await
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_zips_an_odd_number_of_lines() {
+        let zipped = zip_forward("a\nb\nc\nd\ne\n").await;
+        assert_eq!(zipped, pairs(&[("a", "e"), ("b", "d"), ("c", "c")]));
+
+        let zipped = zip_forward("one").await;
+        assert_eq!(zipped, pairs(&[("one", "one")]));
+    }
+
+    #[tokio::test]
+    async fn it_zips_an_even_number_of_lines() {
+        let zipped = zip_forward("ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n").await;
+        let expected = pairs(&[("ABCDEF", "UVWXYZ"), ("GHIJK", "LMNOPQRST")]);
+        assert_eq!(zipped, expected);
+
+        let zipped = zip_forward("\r\nx\r\n\n\n").await;
+        assert_eq!(zipped, pairs(&[("", ""), ("x", "")]));
+
+        assert!(zip_forward("").await.is_empty());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[tokio::test]
+    async fn it_pairs_lines_with_their_digest() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+
+        let digests: Vec<_> = rev_lines
+            .lines_with_digest()
+            .map(|line| {
+                let (line, digest) = line.unwrap();
+                let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+                (line, hex)
+            })
+            .collect()
+            .await;
+
+        assert_eq!(digests.len(), 4);
+        assert_eq!(digests[0].0, "UVWXYZ");
+        assert_eq!(
+            digests[0].1,
+            "f9fefe683187b5c38dc3f20c4978db5d9542f04a26635e6c2b5b8fafad2b9458"
+        );
+        assert_eq!(digests[3].0, "ABCDEF");
+        assert_eq!(
+            digests[3].1,
+            "e9c0f8b575cbfcb42ab3b78ecc87efa3b011d9a5d10b09fa4e96f240bf6a82f5"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_passes_errors_through_async_functions() {
+        let reader = BufReader::new(Cursor::new(b"abc\n\xff\n".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .build(reader)
+            .await
+            .unwrap();
+
+        let results: Vec<_> = rev_lines
+            .then_lines(|line| async move { line.to_uppercase() })
+            .collect()
+            .await;
+
+        assert!(matches!(results[0], Err(Error::NotUtf8(_))));
+        assert_eq!(results[1].as_ref().unwrap(), "ABC");
+    }
+
+    // Without the `futures_util::StreamExt` methods in scope
+    #[cfg(feature = "tokio-stream")]
+    mod tokio_stream {
+        use crate::RevLines;
+        use tokio::fs::File;
+        use tokio::io::BufReader;
+        use tokio_stream::StreamExt;
+
+        #[tokio::test]
+        async fn it_is_usable_with_tokio_stream() {
+            let file = File::open("tests/multi_line_file").await.unwrap();
+            let rev_lines = RevLines::with_capacity(5, BufReader::new(file))
+                .await
+                .unwrap();
+
+            let lines: Result<Vec<String>, _> =
+                rev_lines.to_tokio_stream().skip(1).take(2).collect().await;
+
+            assert_eq!(lines.unwrap(), vec!["LMNOPQRST", "GHIJK"]);
+        }
+    }
+}
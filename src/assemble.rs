@@ -0,0 +1,177 @@
+//! Turning the bytes of every line into items of any type.
+
+use crate::{Error, RevLines};
+use futures_util::Stream;
+use std::pin::Pin;
+use std::string::FromUtf8Error;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek};
+
+/// Turns the bytes of a line into an item, see [`RevLines::assemble_with`].
+///
+/// The bytes are the line without its terminator, after the byte-level line
+/// options like ASCII case folding were applied. Options that work on decoded
+/// strings, like lossy decoding, Unicode case folding and padding, are left to
+/// the assembler.
+pub trait Assembler {
+    /// The item built from every line.
+    type Item;
+
+    /// Build the item for the bytes of one line.
+    fn finish(&self, bytes: Vec<u8>) -> Self::Item;
+}
+
+impl<T, F: Fn(Vec<u8>) -> T> Assembler for F {
+    type Item = T;
+
+    fn finish(&self, bytes: Vec<u8>) -> T {
+        self(bytes)
+    }
+}
+
+/// Keeps the bytes of every line as they are.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawBytes;
+
+impl Assembler for RawBytes {
+    type Item = Vec<u8>;
+
+    fn finish(&self, bytes: Vec<u8>) -> Vec<u8> {
+        bytes
+    }
+}
+
+/// Decodes every line as UTF-8, failing on invalid lines like the plain
+/// [`RevLines`] stream.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Utf8;
+
+impl Assembler for Utf8 {
+    type Item = Result<String, FromUtf8Error>;
+
+    fn finish(&self, bytes: Vec<u8>) -> Self::Item {
+        String::from_utf8(bytes)
+    }
+}
+
+/// Decodes every line as UTF-8, replacing invalid sequences with U+FFFD.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lossy;
+
+impl Assembler for Lossy {
+    type Item = String;
+
+    fn finish(&self, bytes: Vec<u8>) -> String {
+        match String::from_utf8(bytes) {
+            Ok(line) => line,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        }
+    }
+}
+
+/// An async stream of the items an [`Assembler`] builds from the lines,
+/// created by [`RevLines::assemble_with`].
+pub struct Assembled<R, A> {
+    rev_lines: RevLines<R>,
+    assembler: A,
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> RevLines<R> {
+    /// Build the items of the stream from the bytes of every line with
+    /// `assembler` instead of decoding them as strings. Errors reading the
+    /// lines are passed through.
+    pub fn assemble_with<A: Assembler>(self, assembler: A) -> Assembled<R, A> {
+        Assembled {
+            rev_lines: self,
+            assembler,
+        }
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin, A: Assembler + Unpin> Stream for Assembled<R, A> {
+    type Item = Result<A::Item, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Assembled {
+            rev_lines,
+            assembler,
+        } = self.get_mut();
+
+        rev_lines.poll_next_decoded(cx, |rev_lines| {
+            let bytes = std::mem::take(&mut rev_lines.line);
+            Ok(assembler.finish(bytes))
+        })
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use futures_util::StreamExt;
+    use std::io::Cursor;
+    use tokio::fs::File;
+    use tokio::io::BufReader;
+
+    struct LineLength;
+
+    impl Assembler for LineLength {
+        type Item = usize;
+
+        fn finish(&self, bytes: Vec<u8>) -> usize {
+            bytes.len()
+        }
+    }
+
+    #[tokio::test]
+    async fn it_assembles_lines_with_a_custom_assembler() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let lengths: Vec<_> = rev_lines
+            .assemble_with(LineLength)
+            .map(|len| len.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(lengths, vec![6, 9, 5, 6]);
+
+        let reader = BufReader::new(Cursor::new(b"ab\ncd\r\n".to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let firsts: Vec<_> = rev_lines
+            .assemble_with(|bytes: Vec<u8>| bytes[0])
+            .map(|first| first.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(firsts, vec![b'c', b'a']);
+    }
+
+    #[tokio::test]
+    async fn it_provides_built_in_assemblers() {
+        let data = b"ok\n\xff\xfe\n".to_vec();
+
+        let rev_lines = RevLines::new(BufReader::new(Cursor::new(data.clone())))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.assemble_with(RawBytes).collect().await;
+        assert_eq!(lines[0].as_ref().unwrap(), b"\xff\xfe");
+        assert_eq!(lines[1].as_ref().unwrap(), b"ok");
+
+        let rev_lines = RevLines::new(BufReader::new(Cursor::new(data.clone())))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.assemble_with(Utf8).collect().await;
+        assert!(lines[0].as_ref().unwrap().is_err());
+        assert_eq!(lines[1].as_ref().unwrap().as_ref().unwrap(), "ok");
+
+        let rev_lines = RevLines::new(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .assemble_with(Lossy)
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+        assert_eq!(lines, vec!["\u{fffd}\u{fffd}", "ok"]);
+    }
+}
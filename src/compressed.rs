@@ -0,0 +1,66 @@
+//! Reading compressed files by decompressing them to memory first.
+
+use crate::{Error, RevLines, RevLinesBuilder};
+use std::io::Cursor;
+use std::path::Path;
+use tokio::io::BufReader;
+
+impl RevLines<Cursor<Vec<u8>>> {
+    /// Create an async stream of strings from the zstd-compressed file at
+    /// `path`, which may hold several frames one after another. See
+    /// [`RevLinesBuilder::build_from_zstd_path`].
+    pub async fn from_zstd_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        RevLinesBuilder::new().build_from_zstd_path(path).await
+    }
+}
+
+impl RevLinesBuilder {
+    /// Create an async stream of strings from the zstd-compressed file at
+    /// `path`. The whole file is decompressed into memory up front, on the
+    /// calling task, so this suits files whose decompressed size comfortably
+    /// fits in memory. Fails with
+    /// [`Error::Open`] if the file can't be read, and with [`Error::Io`] if
+    /// it isn't valid zstd.
+    pub async fn build_from_zstd_path(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<RevLines<Cursor<Vec<u8>>>, Error> {
+        let path = path.as_ref();
+        let compressed = match tokio::fs::read(path).await {
+            Ok(compressed) => compressed,
+            Err(source) => {
+                let path = path.to_path_buf();
+                return Err(Error::Open { path, source });
+            }
+        };
+
+        let data = zstd::stream::decode_all(&compressed[..])?;
+        self.build(BufReader::new(Cursor::new(data))).await
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn it_reads_zstd_files() {
+        let rev_lines = RevLines::from_zstd_path("tests/multi_line_file.zst")
+            .await
+            .unwrap();
+        let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+
+        assert_eq!(lines, vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"]);
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_files_that_are_not_zstd() {
+        let result = RevLines::from_zstd_path("tests/multi_line_file").await;
+        assert!(matches!(result, Err(Error::Io(_))));
+
+        let result = RevLines::from_zstd_path("tests/missing_file.zst").await;
+        assert!(matches!(result, Err(Error::Open { .. })));
+    }
+}
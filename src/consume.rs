@@ -0,0 +1,449 @@
+//! Helpers that drive a [`RevLines`] stream internally and return a single
+//! value, so callers don't need `StreamExt`.
+
+use crate::{Error, RevLines, RevLinesBuilder};
+use futures_util::{future::poll_fn, Stream};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt, BufReader, SeekFrom};
+
+impl<R: AsyncSeek + AsyncRead + Unpin> RevLines<R> {
+    /// Read the single line whose new line character is at `offset`, from
+    /// the new line character before it, for random access through an index
+    /// of line ends. An `offset` equal to the length of the reader reads the
+    /// last line. Returns `None` if `offset` is past the end of the reader.
+    pub async fn read_record_ending_at(
+        offset: u64,
+        mut reader: BufReader<R>,
+    ) -> Result<Option<String>, Error> {
+        let reader_size = reader.seek(SeekFrom::End(0)).await?;
+        if offset > reader_size {
+            return Ok(None);
+        }
+
+        // The reader isn't at `offset`, so seek to absolute offsets
+        let mut rev_lines = RevLinesBuilder::new()
+            .with_absolute_seeks(true)
+            .build_with_size(reader, offset)?;
+        if offset < reader_size {
+            // `offset` is a new line character, which ends the record
+            rev_lines.at_eof = false;
+            rev_lines.terminated = true;
+            rev_lines.done = false;
+        }
+
+        rev_lines.next_line().await.transpose()
+    }
+
+    /// Find the line closest to the end of the reader that starts with any of
+    /// `prefixes`, together with the index of the prefix it starts with. The
+    /// first matching prefix wins if several do. Stops reading at the match.
+    /// Lines are compared as bytes, so only the matching line is decoded.
+    pub async fn find_last_with_prefix(
+        prefixes: &[&str],
+        reader: BufReader<R>,
+    ) -> Result<Option<(usize, String)>, Error> {
+        let mut rev_lines = RevLines::new(reader).await?;
+
+        let find = |rev_lines: &mut RevLines<R>| {
+            let matched = prefixes
+                .iter()
+                .position(|prefix| rev_lines.line.starts_with(prefix.as_bytes()));
+            match matched {
+                Some(index) => rev_lines.decode_line().map(|line| Some((index, line))),
+                None => Ok(None),
+            }
+        };
+
+        while let Some(found) = poll_fn(|cx| rev_lines.poll_next_decoded(cx, find)).await {
+            if let Some(found) = found? {
+                return Ok(Some(found));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Await the next line of the stream.
+    pub(crate) async fn next_line(&mut self) -> Option<Result<String, Error>> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Await the length in bytes of the next line, without decoding it.
+    async fn next_line_len(&mut self) -> Option<Result<usize, Error>> {
+        let line = poll_fn(|cx| self.poll_next_line(cx)).await?;
+        Some(line.map(|()| self.line.len()))
+    }
+
+    /// Count the lines by their length in bytes, without terminators, in
+    /// buckets that end before each of the ascending `bounds`. Bucket `i`
+    /// counts lengths from `bounds[i - 1]` up to `bounds[i]`, and one more
+    /// bucket at the end counts lengths of at least the last bound. Lines
+    /// aren't decoded, so invalid UTF-8 is counted like anything else.
+    /// Stops at the first error.
+    pub async fn length_histogram(mut self, bounds: &[usize]) -> Result<Vec<u64>, Error> {
+        let mut counts = vec![0; bounds.len() + 1];
+
+        while let Some(len) = self.next_line_len().await {
+            let len = len?;
+            counts[bounds.partition_point(|bound| *bound <= len)] += 1;
+        }
+
+        Ok(counts)
+    }
+
+    /// Like [`RevLines::length_histogram`] with buckets by powers of two:
+    /// bucket 0 counts empty lines and bucket `i` lengths from `2^(i - 1)` up
+    /// to `2^i`. Trailing empty buckets are left out.
+    pub async fn pow2_length_histogram(mut self) -> Result<Vec<u64>, Error> {
+        let mut counts = Vec::new();
+
+        while let Some(len) = self.next_line_len().await {
+            let bucket = (usize::BITS - len?.leading_zeros()) as usize;
+            if bucket >= counts.len() {
+                counts.resize(bucket + 1, 0);
+            }
+            counts[bucket] += 1;
+        }
+
+        Ok(counts)
+    }
+
+    /// Return the line `compare` rates highest. If several lines are equally
+    /// high, the last one read (the earliest in the reader) is returned, like
+    /// `Iterator::max_by`. Stops at the first error.
+    pub async fn max_by<F>(self, mut compare: F) -> Result<Option<String>, Error>
+    where
+        F: FnMut(&str, &str) -> Ordering,
+    {
+        self.reduce(|max, line| match compare(&max, &line) {
+            Ordering::Greater => max,
+            Ordering::Less | Ordering::Equal => line,
+        })
+        .await
+    }
+
+    /// Return the line `compare` rates lowest. If several lines are equally
+    /// low, the first one read (the latest in the reader) is returned, like
+    /// `Iterator::min_by`. Stops at the first error.
+    pub async fn min_by<F>(self, mut compare: F) -> Result<Option<String>, Error>
+    where
+        F: FnMut(&str, &str) -> Ordering,
+    {
+        self.reduce(|min, line| match compare(&min, &line) {
+            Ordering::Greater => line,
+            Ordering::Less | Ordering::Equal => min,
+        })
+        .await
+    }
+
+    /// Check whether the lines are sorted in ascending order of `compare` from
+    /// the first line of the reader to the last, like a chronological log.
+    /// `compare` gets every line along with the one after it in the reader.
+    /// Stops at the first pair out of order, or the first error.
+    pub async fn is_sorted_by<F>(mut self, mut compare: F) -> Result<bool, Error>
+    where
+        F: FnMut(&str, &str) -> Ordering,
+    {
+        let mut after = match self.next_line().await {
+            Some(line) => line?,
+            None => return Ok(true),
+        };
+
+        while let Some(line) = self.next_line().await {
+            let line = line?;
+            if compare(&line, &after) == Ordering::Greater {
+                return Ok(false);
+            }
+            after = line;
+        }
+
+        Ok(true)
+    }
+
+    /// Fold the lines into one by repeatedly applying `f` to the running
+    /// value and the next line read, starting from the first line read.
+    /// Returns `None` for an empty reader. Stops at the first error.
+    pub async fn reduce<F>(mut self, mut f: F) -> Result<Option<String>, Error>
+    where
+        F: FnMut(String, String) -> String,
+    {
+        let mut acc = match self.next_line().await {
+            Some(line) => line?,
+            None => return Ok(None),
+        };
+
+        while let Some(line) = self.next_line().await {
+            acc = f(acc, line?);
+        }
+
+        Ok(Some(acc))
+    }
+
+    /// Join all lines with `sep` into one String allocated with `cap` bytes,
+    /// last line first, or first line first if `forward` is set. With
+    /// [`RevLines::len`] as `cap`, joining with a separator no longer than
+    /// the line terminators never reallocates. Stops at the first error.
+    pub async fn into_string_with_capacity(
+        mut self,
+        cap: usize,
+        sep: &str,
+        forward: bool,
+    ) -> Result<String, Error> {
+        let mut joined = String::with_capacity(cap);
+
+        if forward {
+            let mut lines = Vec::new();
+            while let Some(line) = self.next_line().await {
+                lines.push(line?);
+            }
+
+            for (i, line) in lines.iter().rev().enumerate() {
+                if i > 0 {
+                    joined.push_str(sep);
+                }
+                joined.push_str(line);
+            }
+        } else {
+            let mut first = true;
+            while let Some(line) = self.next_line().await {
+                if !first {
+                    joined.push_str(sep);
+                }
+                joined.push_str(&line?);
+                first = false;
+            }
+        }
+
+        Ok(joined)
+    }
+
+    /// Map every distinct line to the forward line numbers, counting from 1,
+    /// where it appears, in ascending order. Built in a single reverse pass.
+    ///
+    /// Every distinct line is kept in memory along with one number per line
+    /// of the reader, so the map can get about as big as the reader itself
+    /// for files with few repeated lines. Stops at the first error.
+    pub async fn index_by_content(mut self) -> Result<HashMap<String, Vec<usize>>, Error> {
+        // Count lines from the end first, the total is only known at the start
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut count = 0;
+
+        while let Some(line) = self.next_line().await {
+            index.entry(line?).or_default().push(count);
+            count += 1;
+        }
+
+        for numbers in index.values_mut() {
+            for number in numbers.iter_mut() {
+                *number = count - *number;
+            }
+            numbers.reverse();
+        }
+
+        Ok(index)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+    use tokio::fs::File;
+    use tokio::io::BufReader;
+
+    async fn multi_line_file() -> RevLines<File> {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        RevLines::with_capacity(5, BufReader::new(file))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_selects_lines_by_length() {
+        let longest = multi_line_file()
+            .await
+            .max_by(|a, b| a.len().cmp(&b.len()))
+            .await
+            .unwrap();
+        let shortest = multi_line_file()
+            .await
+            .min_by(|a, b| a.len().cmp(&b.len()))
+            .await
+            .unwrap();
+
+        assert_eq!(longest.as_deref(), Some("LMNOPQRST"));
+        assert_eq!(shortest.as_deref(), Some("GHIJK"));
+    }
+
+    #[tokio::test]
+    async fn it_selects_lines_by_content() {
+        let max = multi_line_file().await.max_by(str::cmp).await.unwrap();
+        let min = multi_line_file().await.min_by(str::cmp).await.unwrap();
+
+        assert_eq!(max.as_deref(), Some("UVWXYZ"));
+        assert_eq!(min.as_deref(), Some("ABCDEF"));
+    }
+
+    #[tokio::test]
+    async fn it_checks_whether_lines_are_sorted() {
+        async fn is_sorted(path: &str) -> bool {
+            let file = File::open(path).await.unwrap();
+            let rev_lines = RevLines::with_capacity(7, BufReader::new(file))
+                .await
+                .unwrap();
+            rev_lines.is_sorted_by(str::cmp).await.unwrap()
+        }
+
+        assert!(is_sorted("tests/sorted_file").await);
+        assert!(!is_sorted("tests/unsorted_file").await);
+        assert!(is_sorted("tests/multi_line_file").await);
+        assert!(is_sorted("tests/empty_file").await);
+
+        // By length instead, GHIJK is shorter than ABCDEF before it
+        let sorted = multi_line_file()
+            .await
+            .is_sorted_by(|a, b| a.len().cmp(&b.len()))
+            .await;
+        assert!(!sorted.unwrap());
+    }
+
+    #[tokio::test]
+    async fn it_finds_the_last_line_with_a_prefix() {
+        // The last line isn't valid UTF-8, which is fine as it doesn't match
+        let file = File::open("tests/severity_file").await.unwrap();
+        let found = RevLines::find_last_with_prefix(&["ERROR", "FATAL"], BufReader::new(file))
+            .await
+            .unwrap();
+        assert_eq!(found, Some((1, "FATAL out of memory".to_string())));
+
+        let file = File::open("tests/severity_file").await.unwrap();
+        let found = RevLines::find_last_with_prefix(&["ERROR"], BufReader::new(file))
+            .await
+            .unwrap();
+        assert_eq!(found, Some((0, "ERROR disk almost full".to_string())));
+
+        let file = File::open("tests/severity_file").await.unwrap();
+        let found = RevLines::find_last_with_prefix(&["TRACE"], BufReader::new(file))
+            .await
+            .unwrap();
+        assert_eq!(found, None);
+    }
+
+    #[tokio::test]
+    async fn it_reduces_lines() {
+        let joined = multi_line_file()
+            .await
+            .reduce(|acc, line| acc + "," + &line)
+            .await
+            .unwrap();
+
+        assert_eq!(joined.as_deref(), Some("UVWXYZ,LMNOPQRST,GHIJK,ABCDEF"));
+
+        let reader = BufReader::new(Cursor::new(""));
+        let empty = RevLines::new(reader).await.unwrap().reduce(|a, _| a).await;
+        assert!(matches!(empty, Ok(None)));
+    }
+
+    #[tokio::test]
+    async fn it_joins_lines_into_a_string() {
+        let rev_lines = multi_line_file().await;
+        let cap = rev_lines.len() as usize;
+        let joined = rev_lines
+            .into_string_with_capacity(cap, ",", false)
+            .await
+            .unwrap();
+
+        assert_eq!(joined, "UVWXYZ,LMNOPQRST,GHIJK,ABCDEF");
+        assert_eq!(joined.capacity(), cap);
+
+        let rev_lines = multi_line_file().await;
+        let cap = rev_lines.len() as usize;
+        let joined = rev_lines
+            .into_string_with_capacity(cap, "\n", true)
+            .await
+            .unwrap();
+
+        assert_eq!(joined, "ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ");
+        assert_eq!(joined.capacity(), cap);
+    }
+
+    #[tokio::test]
+    async fn it_counts_line_lengths_in_buckets() {
+        // Lines of 6, 5, 9 and 6 bytes
+        let counts = multi_line_file().await.pow2_length_histogram().await;
+        assert_eq!(counts.unwrap(), vec![0, 0, 0, 3, 1]);
+
+        let counts = multi_line_file().await.length_histogram(&[5, 6, 9]).await;
+        assert_eq!(counts.unwrap(), vec![0, 1, 2, 1]);
+
+        let file = File::open("tests/blank_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let counts = rev_lines.pow2_length_histogram().await;
+        assert_eq!(counts.unwrap(), vec![3, 0, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn it_indexes_lines_by_content() {
+        let file = File::open("tests/repeated_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(3, BufReader::new(file))
+            .await
+            .unwrap();
+
+        let index = rev_lines.index_by_content().await.unwrap();
+
+        assert_eq!(index.len(), 4);
+        assert_eq!(index["apple"], vec![1, 3, 6]);
+        assert_eq!(index["banana"], vec![2, 5]);
+        assert_eq!(index["cherry"], vec![4]);
+        assert_eq!(index[""], vec![7]);
+    }
+
+    #[tokio::test]
+    async fn it_reads_a_record_by_its_end_offset() {
+        // "ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n" has line ends at 6, 12, 22, 29
+        for (offset, expected) in [
+            (6, "ABCDEF"),
+            (12, "GHIJK"),
+            (22, "LMNOPQRST"),
+            (29, "UVWXYZ"),
+        ]
+        .iter()
+        {
+            let file = File::open("tests/multi_line_file").await.unwrap();
+            let record = RevLines::read_record_ending_at(*offset, BufReader::new(file))
+                .await
+                .unwrap();
+            assert_eq!(record.as_deref(), Some(*expected));
+        }
+
+        // Empty records, CRLF and an unterminated last line
+        let data = b"\nab\r\n\nlast";
+        for (offset, expected) in [
+            (0, Some("")),
+            (4, Some("ab")),
+            (5, Some("")),
+            (10, Some("last")),
+            (11, None),
+        ]
+        .iter()
+        {
+            let reader = BufReader::new(Cursor::new(&data[..]));
+            let record = RevLines::read_record_ending_at(*offset, reader)
+                .await
+                .unwrap();
+            assert_eq!(record.as_deref(), *expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_propagates_errors_when_selecting() {
+        let reader = BufReader::new(Cursor::new(b"abc\n\xff\nxyz\n".to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+
+        let result = rev_lines.max_by(str::cmp).await;
+        assert!(matches!(result, Err(Error::NotUtf8(_))));
+    }
+}
@@ -0,0 +1,225 @@
+//! Line indices: the start offsets of the lines of a reader, saved so the
+//! lines can later be read at random without scanning for them.
+//!
+//! An index is a sequence of little-endian `u64`s without a header, one per
+//! line, holding the offset of the first byte of the line in the reader. The
+//! offsets come in the order the lines are read, last line first.
+
+use crate::{Error, RevLines};
+use futures_util::future::poll_fn;
+use std::convert::TryInto;
+use tokio::io::{
+    AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt,
+    BufReader, SeekFrom,
+};
+
+/// Random access to the lines of a reader through an index written by
+/// [`RevLines::write_index_to`]. Created by [`RevLines::from_index`].
+pub struct IndexedRevReader<R> {
+    reader: BufReader<R>,
+    starts: Vec<u64>,
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> RevLines<R> {
+    /// Read all lines and write the index of their start offsets to
+    /// `writer`, in the format described in the [module docs](self).
+    /// Returns the number of lines in the index. Lines aren't decoded, and
+    /// lines left out of the stream aren't indexed.
+    pub async fn write_index_to<W: AsyncWrite + Unpin>(
+        mut self,
+        mut writer: W,
+    ) -> Result<u64, Error> {
+        let mut lines = 0;
+
+        while let Some(start) = poll_fn(|cx| self.poll_next_decoded(cx, |s| Ok(s.line_start))).await
+        {
+            writer.write_all(&start?.to_le_bytes()).await?;
+            lines += 1;
+        }
+        writer.flush().await?;
+
+        Ok(lines)
+    }
+
+    /// Read the whole index from `index` for random access to the lines of
+    /// `reader`, which must be the reader the index was written for. Fails
+    /// with `ErrorKind::InvalidData` if the index doesn't hold whole offsets.
+    pub async fn from_index<I: AsyncRead + Unpin>(
+        reader: BufReader<R>,
+        mut index: I,
+    ) -> Result<IndexedRevReader<R>, Error> {
+        let mut bytes = Vec::new();
+        index.read_to_end(&mut bytes).await?;
+
+        if bytes.len() % 8 != 0 {
+            let e = tokio::io::Error::new(
+                tokio::io::ErrorKind::InvalidData,
+                "line index length isn't a multiple of 8 bytes",
+            );
+            return Err(Error::Io(e));
+        }
+
+        let starts = bytes
+            .chunks_exact(8)
+            .map(|offset| {
+                offset
+                    .try_into()
+                    .map(u64::from_le_bytes)
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        Ok(IndexedRevReader { reader, starts })
+    }
+
+    /// Find the offset where line `n` starts, counting forward from 0 for the
+    /// first line of the reader, by scanning the reader from its start.
+    /// Returns `None` if the reader has `n` lines or fewer; a new line at the
+    /// very end doesn't start another line.
+    pub async fn offset_of_line(n: usize, mut reader: BufReader<R>) -> Result<Option<u64>, Error> {
+        reader.seek(SeekFrom::Start(0)).await?;
+
+        let mut offset = 0;
+        let mut line = 0;
+        loop {
+            let buf = reader.fill_buf().await?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            if line == n {
+                return Ok(Some(offset));
+            }
+
+            let consumed = match buf.iter().position(|ch| *ch == b'\n') {
+                Some(idx) => {
+                    line += 1;
+                    idx + 1
+                }
+                None => buf.len(),
+            };
+            reader.consume(consumed);
+            offset += consumed as u64;
+        }
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> IndexedRevReader<R> {
+    /// The number of lines in the index.
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Whether the index has no lines.
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Read line `n` of the index, counting from 0 for the last line of the
+    /// reader like the stream does, without a scan for its start. Returns
+    /// `None` if `n` is past the end of the index.
+    pub async fn line(&mut self, n: usize) -> Result<Option<String>, Error> {
+        let start = match self.starts.get(n) {
+            Some(start) => *start,
+            None => return Ok(None),
+        };
+
+        self.reader.seek(SeekFrom::Start(start)).await?;
+        let mut line = Vec::new();
+        self.reader.read_until(b'\n', &mut line).await?;
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+
+        Ok(Some(String::from_utf8(line)?))
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use futures_util::StreamExt;
+    use std::io::Cursor;
+    use tokio::fs::File;
+
+    #[tokio::test]
+    async fn it_writes_an_index_of_line_starts() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(4, BufReader::new(file))
+            .await
+            .unwrap();
+
+        let mut index = Vec::new();
+        let lines = rev_lines.write_index_to(&mut index).await.unwrap();
+        assert_eq!(lines, 4);
+        assert_eq!(index.len(), 32);
+
+        let offsets: Vec<_> = index
+            .chunks(8)
+            .map(|offset| u64::from_le_bytes(offset.try_into().unwrap()))
+            .collect();
+        assert_eq!(offsets, vec![23, 13, 7, 0]);
+
+        // Read lines back from their offsets
+        let mut reader = BufReader::new(File::open("tests/multi_line_file").await.unwrap());
+        for (n, expected) in [(1, "LMNOPQRST\n"), (3, "ABCDEF\n")].iter() {
+            reader.seek(SeekFrom::Start(offsets[*n])).await.unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).await.unwrap();
+            assert_eq!(line, *expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_finds_the_offset_of_a_line() {
+        let expected = [Some(0), Some(7), Some(13), Some(23), None, None];
+        for (n, offset) in expected.iter().enumerate() {
+            let file = File::open("tests/multi_line_file").await.unwrap();
+            let reader = BufReader::with_capacity(4, file);
+            assert_eq!(RevLines::offset_of_line(n, reader).await.unwrap(), *offset);
+        }
+
+        // A last line without a new line, and empty lines, are lines too
+        let data = b"ab\n\n\ncd".to_vec();
+        for (n, offset) in [(1, Some(3)), (2, Some(4)), (3, Some(5)), (4, None)].iter() {
+            let reader = BufReader::new(Cursor::new(data.clone()));
+            assert_eq!(RevLines::offset_of_line(*n, reader).await.unwrap(), *offset);
+        }
+
+        let reader = BufReader::new(Cursor::new(Vec::new()));
+        assert_eq!(RevLines::offset_of_line(0, reader).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn it_reads_lines_through_an_index() {
+        let data = b"ABCDEF\nGHIJK\r\n\nLMNOPQRST\nUVWXYZ".to_vec();
+
+        let rev_lines = RevLines::with_capacity(5, BufReader::new(Cursor::new(data.clone())))
+            .await
+            .unwrap();
+        let expected: Vec<_> = rev_lines.map(Result::unwrap).collect().await;
+
+        let rev_lines = RevLines::new(BufReader::new(Cursor::new(data.clone())))
+            .await
+            .unwrap();
+        let mut index = Vec::new();
+        rev_lines.write_index_to(&mut index).await.unwrap();
+
+        let reader = BufReader::new(Cursor::new(data));
+        let mut indexed = RevLines::from_index(reader, &index[..]).await.unwrap();
+        assert_eq!(indexed.len(), expected.len());
+
+        for n in [3, 0, 4, 1, 2, 3].iter() {
+            assert_eq!(indexed.line(*n).await.unwrap().unwrap(), expected[*n]);
+        }
+        assert!(indexed.line(5).await.unwrap().is_none());
+
+        let reader = BufReader::new(Cursor::new(Vec::new()));
+        let result = RevLines::from_index(reader, &index[..7]).await;
+        assert!(matches!(result, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData));
+    }
+}
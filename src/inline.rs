@@ -0,0 +1,223 @@
+//! Lines stored inline in a fixed-size buffer instead of a heap allocation.
+
+use crate::{Error, RevLines};
+use arrayvec::ArrayString;
+use futures_util::Stream;
+use std::fmt;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek};
+
+/// A line stored inline when it fits in `N` bytes, or on the heap otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InlineLine<const N: usize> {
+    Inline(ArrayString<N>),
+    Heap(String),
+}
+
+impl<const N: usize> InlineLine<N> {
+    /// Borrow the line as a string slice.
+    pub fn as_str(&self) -> &str {
+        match self {
+            InlineLine::Inline(s) => s.as_str(),
+            InlineLine::Heap(s) => s.as_str(),
+        }
+    }
+
+    /// Whether the line is stored inline.
+    pub fn is_inline(&self) -> bool {
+        matches!(self, InlineLine::Inline(_))
+    }
+}
+
+impl<const N: usize> Deref for InlineLine<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> fmt::Display for InlineLine<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An async stream of [`InlineLine`]s, created by [`RevLines::inline_lines`].
+pub struct InlineLines<R, const N: usize> {
+    rev_lines: RevLines<R>,
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> RevLines<R> {
+    /// Yield lines of up to `N` bytes as [`ArrayString`]s instead of
+    /// allocating a `String` for each of them. Longer lines fall back to the
+    /// heap.
+    pub fn inline_lines<const N: usize>(self) -> InlineLines<R, N> {
+        InlineLines { rev_lines: self }
+    }
+
+    /// Whether lines have to be processed as strings after being decoded.
+    fn transforms_decoded(&self) -> bool {
+        #[cfg(feature = "unicode-width")]
+        if self.line_options.padding.is_some() {
+            return true;
+        }
+
+        #[cfg(feature = "unicode-normalization")]
+        if self.line_options.normalization.is_some() {
+            return true;
+        }
+
+        if self.line_options.max_chars.is_some() {
+            return true;
+        }
+
+        self.line_options.unicode_case && self.line_options.case.is_some()
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin, const N: usize> Stream for InlineLines<R, N> {
+    type Item = Result<InlineLine<N>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let rev_lines = &mut self.get_mut().rev_lines;
+
+        rev_lines.poll_next_decoded(cx, |rev_lines| {
+            // Short lines are copied out so the line buffer can be reused
+            if rev_lines.line.len() <= N && !rev_lines.transforms_decoded() {
+                if let Ok(s) = std::str::from_utf8(&rev_lines.line) {
+                    if let Ok(s) = ArrayString::from(s) {
+                        return Ok(InlineLine::Inline(s));
+                    }
+                }
+            }
+
+            let line = rev_lines.decode_line()?;
+            match ArrayString::from(&line) {
+                Ok(s) => Ok(InlineLine::Inline(s)),
+                Err(_) => Ok(InlineLine::Heap(line)),
+            }
+        })
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use crate::{CharOverflow, ErrorMode, RevLinesBuilder};
+    use futures_util::StreamExt;
+    use std::io::Cursor;
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn it_stores_short_lines_inline() {
+        let reader = BufReader::new(Cursor::new("short\nthis line is too long\nab\n"));
+        let rev_lines = RevLines::with_capacity(4, reader).await.unwrap();
+        let lines: Vec<_> = rev_lines
+            .inline_lines::<8>()
+            .map(|line| line.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].is_inline() && lines[0].as_str() == "ab");
+        assert!(!lines[1].is_inline() && lines[1].as_str() == "this line is too long");
+        assert!(lines[2].is_inline() && lines[2].as_str() == "short");
+    }
+
+    #[tokio::test]
+    async fn it_reports_invalid_utf8_in_short_lines() {
+        let reader = BufReader::new(Cursor::new(b"ok\n\xff\xfe\n".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.inline_lines::<8>().collect().await;
+
+        assert!(matches!(lines[0], Err(Error::NotUtf8(_))));
+        assert_eq!(lines[1].as_ref().unwrap().as_str(), "ok");
+    }
+
+    #[tokio::test]
+    async fn it_reports_a_utf8_sequence_truncated_at_the_end() {
+        let reader = BufReader::new(Cursor::new(b"ok\n\xe2\x82".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.inline_lines::<8>().collect().await;
+
+        assert!(matches!(lines[0], Err(Error::NotUtf8(_))));
+        assert_eq!(lines[1].as_ref().unwrap().as_str(), "ok");
+    }
+
+    #[tokio::test]
+    async fn it_limits_the_chars_of_short_lines() {
+        let reader = BufReader::new(Cursor::new("abcdef\nab\n"));
+        let rev_lines = RevLinesBuilder::new()
+            .max_chars(3, CharOverflow::Truncate)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .inline_lines::<16>()
+            .map(|line| line.unwrap().to_string())
+            .collect()
+            .await;
+        assert_eq!(lines, vec!["ab", "abc"]);
+
+        let reader = BufReader::new(Cursor::new("abcdef\nab\n"));
+        let rev_lines = RevLinesBuilder::new()
+            .max_chars(3, CharOverflow::Reject)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.inline_lines::<16>().collect().await;
+        assert_eq!(lines[0].as_ref().unwrap().as_str(), "ab");
+        assert!(matches!(
+            lines[1],
+            Err(Error::TooManyChars { offset: 0, max: 3 })
+        ));
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[tokio::test]
+    async fn it_normalizes_short_lines() {
+        use crate::Normalization;
+
+        let reader = BufReader::new(Cursor::new("e\u{301}\n"));
+        let rev_lines = RevLinesBuilder::new()
+            .normalize(Normalization::Nfc)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .inline_lines::<16>()
+            .map(|line| line.unwrap().to_string())
+            .collect()
+            .await;
+        assert_eq!(lines, vec!["\u{e9}"]);
+    }
+
+    #[tokio::test]
+    async fn it_skips_lines_whose_errors_are_collected() {
+        let reader = BufReader::new(Cursor::new(b"ok\n\xff\xfe\nfine\n".to_vec()));
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Collect)
+            .build(reader)
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines
+            .inline_lines::<8>()
+            .map(|line| line.unwrap().to_string())
+            .collect()
+            .await;
+
+        assert_eq!(lines, vec!["fine", "ok"]);
+    }
+}
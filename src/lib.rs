@@ -1,265 +1,3445 @@
-//! This library provides an async stream for reading files or any `BufReader` line by line with buffering in reverse.
-//!
-//! It's an async tokio version of [rev_lines](https://github.com/mjc-gh/rev_lines).
-//!
-//! ### Example
-//!
-//! ```
-//! use futures_util::{pin_mut, StreamExt};
-//! use tokio::{fs::File, io::BufReader};
-//! use tokio_rev_lines::RevLines;
-//!
-//! #[tokio::main]
-//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     let file = File::open("tests/multi_line_file").await?;
-//!     let rev_lines = RevLines::new(BufReader::new(file)).await?;
-//!     pin_mut!(rev_lines);
-//!
-//!     while let Some(line) = rev_lines.next().await {
-//!         println!("{}", line?);
-//!     }
-//!
-//!     Ok(())
-//! }
-//! ```
-//!
-//! This method uses logic borrowed from [uutils/coreutils
-//! tail](https://github.com/uutils/coreutils/blob/f2166fed0ad055d363aedff6223701001af090d3/src/tail/tail.rs#L399-L402)
-
-use futures_util::{stream, Stream};
-use std::cmp::min;
-use thiserror::Error;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, BufReader, SeekFrom};
-
-static DEFAULT_SIZE: usize = 4096;
-
-static LF_BYTE: u8 = '\n' as u8;
-static CR_BYTE: u8 = '\r' as u8;
-
-/// Custom error types
-#[derive(Error, Debug)]
-pub enum Error {
-    #[error(transparent)]
-    Io(#[from] tokio::io::Error),
-
-    #[error(transparent)]
-    NotUtf8(#[from] std::string::FromUtf8Error),
-}
-
-/// `RevLines` struct
-pub struct RevLines<R> {
-    reader: BufReader<R>,
-    reader_pos: u64,
-    buf_size: u64,
-}
-
-impl<R: AsyncSeek + AsyncRead + Unpin> RevLines<R> {
-    /// Create an async stream of strings from a `BufReader<R>`. Internal
-    /// buffering for iteration will default to 4096 bytes at a time.
-    pub async fn new(
-        reader: BufReader<R>,
-    ) -> Result<impl Stream<Item = Result<String, Error>>, Error> {
-        RevLines::with_capacity(DEFAULT_SIZE, reader).await
-    }
-
-    /// Create an async stream of strings from a `BufReader<R>`. Internal
-    /// buffering for iteration will use `cap` bytes at a time.
-    pub async fn with_capacity(
-        cap: usize,
-        mut reader: BufReader<R>,
-    ) -> Result<impl Stream<Item = Result<String, Error>>, Error> {
-        // Seek to end of reader now
-        let reader_size = reader.seek(SeekFrom::End(0)).await?;
-
-        let mut rev_lines = RevLines {
-            reader: reader,
-            reader_pos: reader_size,
-            buf_size: cap as u64,
-        };
-
-        // Handle any trailing new line characters for the reader
-        // so the first next call does not return Some("")
-
-        // Read at most 2 bytes
-        let end_size = min(reader_size, 2);
-        let end_buf = rev_lines.read_to_buffer(end_size).await?;
-
-        if end_size == 1 {
-            if end_buf[0] != LF_BYTE {
-                rev_lines.move_reader_position(1).await?;
-            }
-        } else if end_size == 2 {
-            if end_buf[0] != CR_BYTE {
-                rev_lines.move_reader_position(1).await?;
-            }
-
-            if end_buf[1] != LF_BYTE {
-                rev_lines.move_reader_position(1).await?;
-            }
-        }
-
-        let stream = stream::unfold(rev_lines, |mut rev_lines| async {
-            match rev_lines.next_line().await {
-                Some(line) => Some((line, rev_lines)),
-                None => None,
-            }
-        });
-
-        Ok(stream)
-    }
-
-    async fn read_to_buffer(&mut self, size: u64) -> Result<Vec<u8>, tokio::io::Error> {
-        let mut buf = vec![0; size as usize];
-        let offset = -(size as i64);
-
-        self.reader.seek(SeekFrom::Current(offset)).await?;
-        self.reader.read_exact(&mut buf[0..(size as usize)]).await?;
-        self.reader.seek(SeekFrom::Current(offset)).await?;
-
-        self.reader_pos -= size;
-
-        Ok(buf)
-    }
-
-    async fn move_reader_position(&mut self, offset: u64) -> Result<(), tokio::io::Error> {
-        self.reader.seek(SeekFrom::Current(offset as i64)).await?;
-        self.reader_pos += offset;
-
-        Ok(())
-    }
-
-    async fn next_line(&mut self) -> Option<Result<String, Error>> {
-        let mut result: Vec<u8> = Vec::new();
-
-        'outer: loop {
-            if self.reader_pos < 1 {
-                if result.len() > 0 {
-                    break;
-                }
-
-                return None;
-            }
-
-            // Read the of minimum between the desired
-            // buffer size or remaining length of the reader
-            let size = min(self.buf_size, self.reader_pos);
-
-            match self.read_to_buffer(size).await {
-                Ok(buf) => {
-                    for (idx, ch) in (&buf).iter().enumerate().rev() {
-                        // Found a new line character to break on
-                        if *ch == LF_BYTE {
-                            let mut offset = idx as u64;
-
-                            // Add an extra byte cause of CR character
-                            if idx > 1 && buf[idx - 1] == CR_BYTE {
-                                offset -= 1;
-                            }
-
-                            match self.reader.seek(SeekFrom::Current(offset as i64)).await {
-                                Ok(_) => {
-                                    self.reader_pos += offset;
-
-                                    break 'outer;
-                                }
-
-                                Err(e) => return Some(Err(Error::Io(e))),
-                            }
-                        } else {
-                            result.push(ch.clone());
-                        }
-                    }
-                }
-
-                Err(e) => return Some(Err(Error::Io(e))),
-            }
-        }
-
-        // Reverse the results since they were written backwards
-        result.reverse();
-
-        // Convert to a String
-        match String::from_utf8(result) {
-            Ok(s) => Some(Ok(s)),
-            Err(e) => Some(Err(Error::NotUtf8(e))),
-        }
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    use futures_util::{pin_mut, StreamExt};
-    use tokio::fs::File;
-
-    #[tokio::test]
-    async fn it_handles_empty_files() {
-        let file = File::open("tests/empty_file").await.unwrap();
-        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
-        let results = vec![];
-
-        assert_stream_eq(rev_lines, results).await;
-    }
-
-    #[tokio::test]
-    async fn it_handles_file_with_one_line() {
-        let file = File::open("tests/one_line_file").await.unwrap();
-        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
-        let results = vec!["ABCD"];
-
-        assert_stream_eq(rev_lines, results).await;
-    }
-
-    #[tokio::test]
-    async fn it_handles_file_with_multi_lines() {
-        let file = File::open("tests/multi_line_file").await.unwrap();
-        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
-        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
-
-        assert_stream_eq(rev_lines, results).await;
-    }
-
-    #[tokio::test]
-    async fn it_handles_file_with_blank_lines() {
-        let file = File::open("tests/blank_line_file").await.unwrap();
-        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
-        let results = vec!["", "", "XYZ", "", "ABCD"];
-
-        assert_stream_eq(rev_lines, results).await;
-    }
-
-    #[tokio::test]
-    async fn it_handles_file_with_multi_lines_and_with_capacity() {
-        let file = File::open("tests/multi_line_file").await.unwrap();
-        let rev_lines = RevLines::with_capacity(5, BufReader::new(file))
-            .await
-            .unwrap();
-        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
-
-        assert_stream_eq(rev_lines, results).await;
-    }
-
-    async fn assert_stream_eq(
-        rev_lines: impl Stream<Item = Result<String, Error>>,
-        results: Vec<&str>,
-    ) {
-        pin_mut!(rev_lines);
-
-        for result in results {
-            let equals = if let Some(Ok(line)) = rev_lines.next().await {
-                line == result.to_string()
-            } else {
-                false
-            };
-            assert!(equals)
-        }
-
-        let equals = if let None = rev_lines.next().await {
-            true
-        } else {
-            false
-        };
-        assert!(equals);
-    }
-}
+//! This library provides an async stream for reading files or any `BufReader` line by line with buffering in reverse.
+//!
+//! It's an async tokio version of [rev_lines](https://github.com/mjc-gh/rev_lines).
+//!
+//! ### Example
+//!
+//! ```
+//! use futures_util::{pin_mut, StreamExt};
+//! use tokio::{fs::File, io::BufReader};
+//! use tokio_rev_lines::RevLines;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let file = File::open("tests/multi_line_file").await?;
+//!     let rev_lines = RevLines::new(BufReader::new(file)).await?;
+//!     pin_mut!(rev_lines);
+//!
+//!     while let Some(line) = rev_lines.next().await {
+//!         println!("{}", line?);
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! Streams with non-default settings are created through [`RevLinesBuilder`]:
+//!
+//! ```
+//! use futures_util::{pin_mut, StreamExt};
+//! use tokio::{fs::File, io::BufReader};
+//! use tokio_rev_lines::RevLinesBuilder;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let file = File::open("tests/multi_line_file").await?;
+//!     let rev_lines = RevLinesBuilder::new()
+//!         .capacity(1024)
+//!         .with_absolute_seeks(true)
+//!         .build(BufReader::new(file))
+//!         .await?;
+//!     pin_mut!(rev_lines);
+//!
+//!     assert_eq!(rev_lines.next().await.transpose()?.as_deref(), Some("UVWXYZ"));
+//!
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ### Features
+//!
+//! - `arrayvec`: [`RevLines::inline_lines`] yields short lines without a heap allocation.
+//! - `tokio-util`: [`RevLines::with_cancellation`] ends the stream on a `CancellationToken`.
+//! - `sha2`: [`RevLines::lines_with_digest`] pairs every line with its SHA-256 digest.
+//! - `fs`: [`RevLines::from_paths`] reads several files one after another.
+//! - `time`: [`RevLines::with_deadline`] bounds how long the whole read may take, and
+//!   [`RevLines::with_retry`] retries transient errors.
+//! - `unicode-width`: [`RevLinesBuilder::pad_to`] pads lines to a display width.
+//! - `tokio-stream`: [`RevLines::to_tokio_stream`] for `tokio_stream::StreamExt` chains.
+//! - `rt`: [`RevLines::with_prefetch`], [`RevLines::prefetch_with_profile`] and
+//!   [`RevLines::broadcast`] read lines in a background task.
+//! - `zstd`: [`RevLines::from_zstd_path`] reads zstd-compressed files.
+//! - `debug-chunks`: [`RevLines::lines_with_chunks`] reports the chunks every line was read from.
+//! - `compress-lines`: [`RevLinesBuilder::compress_lines_over`] keeps huge lines compressed while
+//!   they are assembled.
+//! - `regex`: [`RevLines::resync_anchor`] joins lines into records that start with a pattern.
+//! - `unicode-normalization`: [`RevLinesBuilder::normalize`] brings lines to NFC or NFD.
+//!
+//! This method uses logic borrowed from [uutils/coreutils
+//! tail](https://github.com/uutils/coreutils/blob/f2166fed0ad055d363aedff6223701001af090d3/src/tail/tail.rs#L399-L402)
+
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::expect_used))]
+
+use futures_util::Stream;
+use std::cmp::{max, min};
+#[cfg(any(feature = "tokio-util", feature = "time"))]
+use std::future::Future;
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{
+    copy, sink, AsyncBufReadExt, AsyncRead, AsyncSeek, AsyncSeekExt, BufReader, ReadBuf, SeekFrom,
+};
+#[cfg(feature = "time")]
+use tokio::time::{sleep, sleep_until, Instant, Sleep};
+#[cfg(feature = "tokio-util")]
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+mod adapters;
+mod assemble;
+#[cfg(feature = "zstd")]
+mod compressed;
+mod consume;
+mod index;
+#[cfg(feature = "arrayvec")]
+mod inline;
+#[cfg(all(test, loom))]
+mod loom;
+#[cfg(feature = "fs")]
+mod paths;
+#[cfg(feature = "rt")]
+mod prefetch;
+mod read_at;
+mod record;
+mod shared;
+
+#[cfg(feature = "debug-chunks")]
+pub use adapters::LineChunk;
+pub use adapters::{
+    Completion, Delimiter, Heartbeats, LinePosition, RevItem, SkipHeader, TrackCompletion, Windows2,
+};
+pub use assemble::{Assembled, Assembler, Lossy, RawBytes, Utf8};
+pub use index::IndexedRevReader;
+#[cfg(feature = "arrayvec")]
+pub use inline::{InlineLine, InlineLines};
+#[cfg(feature = "fs")]
+pub use paths::RevPaths;
+#[cfg(feature = "rt")]
+pub use prefetch::{BroadcastLine, Prefetch};
+pub use read_at::{ConcurrentReader, ReadAt, ReadAtFuture};
+pub use record::{IoLog, IoOp, RecordingReader, ReplayReader};
+pub use shared::SharedReader;
+
+static DEFAULT_SIZE: usize = 4096;
+
+static LF_BYTE: u8 = b'\n';
+static CR_BYTE: u8 = b'\r';
+
+/// Custom error types
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] tokio::io::Error),
+
+    #[error(transparent)]
+    NotUtf8(#[from] std::string::FromUtf8Error),
+
+    #[error("invalid UTF-8 at byte offset {offset}")]
+    InvalidUtf8 {
+        offset: u64,
+        #[source]
+        source: std::string::FromUtf8Error,
+    },
+
+    #[error("line at byte offset {offset} has more than {max} characters")]
+    TooManyChars { offset: u64, max: usize },
+
+    #[error("reader ends at byte offset {offset} in the middle of a UTF-8 sequence")]
+    TruncatedUtf8 {
+        offset: u64,
+        #[source]
+        source: std::string::FromUtf8Error,
+    },
+
+    #[error("reader reported offset {actual} after a seek, expected {expected}")]
+    InconsistentSeek { expected: u64, actual: u64 },
+
+    #[error("reader ended at offset {offset}, short of the {len} bytes it had")]
+    Shrunk { offset: u64, len: u64 },
+
+    #[error("more than {max} lines would be held for lookahead")]
+    LookaheadExceeded { max: usize },
+
+    #[error("deadline for reading the lines has passed")]
+    DeadlineExceeded,
+
+    #[error("failed to open {}", path.display())]
+    Open {
+        path: std::path::PathBuf,
+        #[source]
+        source: tokio::io::Error,
+    },
+}
+
+/// Error of a stream of parsed lines, see [`RevLines::parse_lines`].
+#[derive(Error, Debug)]
+pub enum ParseLineError<E> {
+    #[error(transparent)]
+    Read(#[from] Error),
+
+    #[error("failed to parse line: {0}")]
+    Parse(E),
+}
+
+/// Case folding applied to every line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Case {
+    Lower,
+    Upper,
+}
+
+/// What the stream does on errors, see [`RevLinesBuilder::error_mode`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorMode {
+    /// Yield the error of a line that can't be decoded in place of the line,
+    /// and go on reading with the line before it. I/O errors end the stream.
+    /// This is how the stream handled errors before there were error modes.
+    Yield,
+    /// Yield the first error of any kind and end the stream after it.
+    #[default]
+    Stop,
+    /// Keep lines that can't be decoded out of the stream and collect their
+    /// errors in [`RevLines::errors`], so the other lines keep coming. I/O
+    /// errors are still yielded and end the stream.
+    Collect,
+}
+
+/// What to do with NUL bytes inside lines, see
+/// [`RevLinesBuilder::nul_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NulPolicy {
+    /// Keep NUL bytes in the lines like any other byte.
+    #[default]
+    Keep,
+    /// Remove NUL bytes from the lines.
+    StripNuls,
+    /// End a line at a NUL byte like at a new line character. The NUL isn't
+    /// part of either line.
+    SplitOnNul,
+}
+
+/// What to do with lines over the character limit of
+/// [`RevLinesBuilder::max_chars`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharOverflow {
+    /// Yield an [`Error::TooManyChars`] in place of the line.
+    Reject,
+    /// Cut the line after its last allowed character.
+    Truncate,
+}
+
+/// How the stream positions the reader before every chunk read, see
+/// [`RevLinesBuilder::seek_strategy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SeekStrategy {
+    /// Move the reader back and forth with `SeekFrom::Current`.
+    #[default]
+    Relative,
+    /// Seek to every chunk with `SeekFrom::Start`.
+    Absolute,
+    /// Time both kinds of seeks once while the stream is built, and use the
+    /// faster one.
+    Auto,
+}
+
+/// Unicode normalization form of the lines, see [`RevLinesBuilder::normalize`].
+#[cfg(feature = "unicode-normalization")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// Canonical composition, like `é` as one precomposed character.
+    Nfc,
+    /// Canonical decomposition, like `é` as `e` and a combining accent.
+    Nfd,
+}
+
+/// A trade-off between latency and throughput, see
+/// [`RevLinesBuilder::profile`].
+///
+/// A profile sets the capacity, [`RevLinesBuilder::yield_interval`] and
+/// [`RevLinesBuilder::whole_read_threshold`], which sizes the only chunk of
+/// a small reader to the whole reader. The number of lines prefetched only
+/// applies to `RevLines::prefetch_with_profile` from the `rt` feature, which
+/// reads ahead in a background task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// Chunks of 1 KiB, letting other tasks run every 16 lines, and
+    /// prefetching 1 line, for the first lines as soon as possible. Only
+    /// readers that fit in one chunk are read in one go.
+    LowLatency,
+    /// Chunks of 8 KiB, readers of up to 64 KiB read in one go, letting other
+    /// tasks run every 256 lines, and prefetching 64 lines.
+    Balanced,
+    /// Chunks of 64 KiB, readers of up to 1 MiB read in one go, never letting
+    /// other tasks run in between, and prefetching 1024 lines, for reading
+    /// whole files fast.
+    HighThroughput,
+}
+
+impl Profile {
+    /// The number of lines to read ahead for this profile.
+    #[cfg(feature = "rt")]
+    fn prefetch_depth(self) -> usize {
+        match self {
+            Profile::LowLatency => 1,
+            Profile::Balanced => 64,
+            Profile::HighThroughput => 1024,
+        }
+    }
+}
+
+/// Side of a line that [`RevLinesBuilder::pad_to`] adds spaces to.
+#[cfg(feature = "unicode-width")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pad {
+    /// Add spaces before the line, aligning it to the right.
+    Left,
+    /// Add spaces after the line, aligning it to the left.
+    Right,
+}
+
+/// How to retry reads and seeks of the stream that fail with a transient
+/// I/O error: `WouldBlock`, `TimedOut` or `Interrupted`. Other errors are
+/// yielded right away.
+#[cfg(feature = "time")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times to retry a failed read or seek before yielding the error.
+    pub max_retries: u32,
+    /// How long to wait before the first retry. The wait doubles with every
+    /// retry after it.
+    pub backoff: Duration,
+}
+
+#[cfg(feature = "time")]
+impl RetryPolicy {
+    /// Retry up to `max_retries` times, waiting `backoff` before the first retry.
+    pub fn new(max_retries: u32, backoff: Duration) -> Self {
+        RetryPolicy {
+            max_retries,
+            backoff,
+        }
+    }
+
+    /// Wait before retry number `retry`, counting from 0.
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(1 << retry.min(31))
+    }
+}
+
+/// Settings for how a line is processed once all of its bytes are read.
+#[derive(Clone, Debug, Default)]
+struct LineOptions {
+    case: Option<Case>,
+    unicode_case: bool,
+    max_line_len: Option<usize>,
+    max_chars: Option<(usize, CharOverflow)>,
+    strip_space_before_crlf: bool,
+    strip_lone_cr: bool,
+    nul_policy: NulPolicy,
+    // Byte that makes a new line character right after it part of the line
+    escape: Option<u8>,
+    skip_empty: bool,
+    // Also skip lines of only whitespace
+    skip_blank: bool,
+    // Replacement for invalid UTF-8 sequences, if they don't cause errors
+    lossy: Option<char>,
+    #[cfg(feature = "unicode-normalization")]
+    normalization: Option<Normalization>,
+    #[cfg(feature = "unicode-width")]
+    padding: Option<(usize, Pad)>,
+}
+
+/// Builder for a [`RevLines`] stream with non-default settings.
+#[derive(Clone, Debug)]
+pub struct RevLinesBuilder {
+    capacity: usize,
+    seek_strategy: SeekStrategy,
+    strict: bool,
+    fallback_length: bool,
+    known_length: Option<u64>,
+    trailer_len: u64,
+    stop_pos: u64,
+    whole_read_threshold: Option<u64>,
+    ring_start: Option<u64>,
+    yield_interval: usize,
+    max_lookahead: Option<usize>,
+    error_mode: ErrorMode,
+    match_needle: Option<Vec<u8>>,
+    line_options: LineOptions,
+    #[cfg(feature = "tokio-util")]
+    cancellation: Option<CancellationToken>,
+    #[cfg(feature = "time")]
+    deadline: Option<Instant>,
+    #[cfg(feature = "time")]
+    retry: Option<RetryPolicy>,
+    #[cfg(feature = "compress-lines")]
+    compress_threshold: Option<usize>,
+    #[cfg(feature = "fs")]
+    memory_budget: Option<usize>,
+}
+
+impl Default for RevLinesBuilder {
+    fn default() -> Self {
+        RevLinesBuilder {
+            capacity: DEFAULT_SIZE,
+            seek_strategy: SeekStrategy::Relative,
+            strict: false,
+            fallback_length: false,
+            known_length: None,
+            trailer_len: 0,
+            stop_pos: 0,
+            whole_read_threshold: None,
+            ring_start: None,
+            yield_interval: 0,
+            max_lookahead: None,
+            error_mode: ErrorMode::Stop,
+            match_needle: None,
+            line_options: LineOptions::default(),
+            #[cfg(feature = "tokio-util")]
+            cancellation: None,
+            #[cfg(feature = "time")]
+            deadline: None,
+            #[cfg(feature = "time")]
+            retry: None,
+            #[cfg(feature = "compress-lines")]
+            compress_threshold: None,
+            #[cfg(feature = "fs")]
+            memory_budget: None,
+        }
+    }
+}
+
+impl RevLinesBuilder {
+    /// Create a builder with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Internal buffering for iteration will use `cap` bytes at a time.
+    /// Defaults to 4096 bytes. A `cap` of 0 is treated as 1.
+    pub fn capacity(mut self, cap: usize) -> Self {
+        self.capacity = cap;
+        self
+    }
+
+    /// Position the reader with `SeekFrom::Start(absolute)` before every
+    /// chunk read instead of moving it back and forth with
+    /// `SeekFrom::Current(relative)`. Useful for readers that only handle
+    /// absolute seeks correctly. Defaults to `false`. Shorthand for
+    /// [`RevLinesBuilder::seek_strategy`] with [`SeekStrategy::Absolute`] or
+    /// [`SeekStrategy::Relative`].
+    pub fn with_absolute_seeks(mut self, absolute_seeks: bool) -> Self {
+        self.seek_strategy = if absolute_seeks {
+            SeekStrategy::Absolute
+        } else {
+            SeekStrategy::Relative
+        };
+        self
+    }
+
+    /// Choose the seeks that position the reader before every chunk read.
+    /// [`SeekStrategy::Auto`] probes the reader while the stream is built:
+    /// it times a few seeks of each kind to the end of the reader, where it
+    /// already is, and picks absolute seeks only if they were faster. The
+    /// probe costs those seeks up front, and may pick wrong for readers whose
+    /// seek time varies a lot. Defaults to [`SeekStrategy::Relative`].
+    pub fn seek_strategy(mut self, strategy: SeekStrategy) -> Self {
+        self.seek_strategy = strategy;
+        self
+    }
+
+    /// Check the offset the reader reports after every seek, and fail with
+    /// [`Error::InconsistentSeek`] if it isn't the expected one. Catches
+    /// buggy reader implementations early. Also reports lines that aren't
+    /// valid UTF-8 as [`Error::InvalidUtf8`], which carries the absolute
+    /// offset of the first invalid byte in the reader, instead of
+    /// [`Error::NotUtf8`], or as [`Error::TruncatedUtf8`] if the reader ends
+    /// in the middle of a multi-byte character. Defaults to `false`.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// If the reader can't seek to its end, find its length by reading it
+    /// to the end instead. The bytes are counted from the reader's current
+    /// position, which should be its start. Only use this with bounded
+    /// readers. Defaults to `false`.
+    pub fn fallback_length(mut self, fallback_length: bool) -> Self {
+        self.fallback_length = fallback_length;
+        self
+    }
+
+    /// Use `len` as the length of the reader instead of seeking to its end,
+    /// for readers where `SeekFrom::End` is expensive or unsupported but the
+    /// length is known some other way. The reader is positioned with
+    /// `SeekFrom::Start(len)` instead.
+    pub fn known_length(mut self, len: u64) -> Self {
+        self.known_length = Some(len);
+        self
+    }
+
+    /// Leave out the last `n` bytes of the reader, like a fixed-size binary
+    /// trailer after a text log, and read the lines of the bytes before them.
+    /// The trailer is never read. Applies after the length of the reader is
+    /// found, so it also shortens a [`RevLinesBuilder::known_length`].
+    /// Defaults to 0.
+    pub fn trailer_bytes(mut self, n: u64) -> Self {
+        self.trailer_len = n;
+        self
+    }
+
+    /// Read the lines only down to byte `offset`, which should be the start
+    /// of a line, as if the reader started there. The bytes before it are
+    /// never read, and the line starting at `offset` is the last one
+    /// yielded. An `offset` past the end leaves no lines. Defaults to 0.
+    pub fn stop_at(mut self, offset: u64) -> Self {
+        self.stop_pos = offset;
+        self
+    }
+
+    /// Read readers of up to `len` bytes with a single read from their start
+    /// instead of a chunk at a time from their end, which saves the seeks
+    /// between chunks for small files. The lines are the same either way.
+    /// Defaults to the capacity, since a reader that fits in one chunk is
+    /// already read in one go.
+    pub fn whole_read_threshold(mut self, len: u64) -> Self {
+        self.whole_read_threshold = Some(len);
+        self
+    }
+
+    /// Read the reader as a ring buffer whose next write goes to offset
+    /// `write_pos`: start reading backwards at `write_pos`, wrap around to the
+    /// end of the reader at offset 0, and stop on getting back to `write_pos`.
+    /// A line may continue across the wrap. Implies absolute seeks.
+    pub fn ring(mut self, write_pos: u64) -> Self {
+        self.ring_start = Some(write_pos);
+        self
+    }
+
+    /// Return `Poll::Pending` once after every `k` lines, waking the task right
+    /// away, so the task can be polled again after others ran. Readers that
+    /// never wait, like a `Cursor` over a large buffer, would otherwise keep
+    /// the task busy until all lines are read. A `k` of 0, the default, never
+    /// yields.
+    pub fn yield_interval(mut self, k: usize) -> Self {
+        self.yield_interval = k;
+        self
+    }
+
+    /// Bound the lines that adapters reading ahead of the lines they yield,
+    /// [`RevLines::forward_lines_buffered`] and
+    /// [`RevLines::skip_header_lines`], may hold at once. Instead of holding
+    /// more than `n` lines they yield an [`Error::LookaheadExceeded`] and
+    /// end, so a misuse can't grow memory without bound. Unbounded by
+    /// default.
+    pub fn max_lookahead(mut self, n: usize) -> Self {
+        self.max_lookahead = Some(n);
+        self
+    }
+
+    /// Choose between yielding errors as they come, ending the stream on the
+    /// first one, or collecting errors of lines that can't be decoded while
+    /// the other lines keep coming, for checking a whole file in one pass.
+    /// Applies to the stream and to every adapter that decodes lines.
+    /// Defaults to [`ErrorMode::Stop`].
+    pub fn error_mode(mut self, mode: ErrorMode) -> Self {
+        self.error_mode = mode;
+        self
+    }
+
+    /// Count the lines that contain `needle` while they are read, for metrics
+    /// like the number of errors in the tail without a second scan. Lines
+    /// are searched byte by byte, after the byte-level line options like
+    /// ASCII case folding; lines left out of the stream aren't counted.
+    /// [`RevLines::match_count`] has the count so far.
+    pub fn count_matches(mut self, needle: &str) -> Self {
+        self.match_needle = Some(needle.as_bytes().to_vec());
+        self
+    }
+
+    /// Leave out lines longer than `max_len` bytes, for example giant base64
+    /// blobs in a log. Skipped lines are never held in memory in full, nor
+    /// converted to strings. [`RevLines::skipped_lines`] counts them.
+    pub fn skip_lines_longer_than(mut self, max_len: usize) -> Self {
+        self.line_options.max_line_len = Some(max_len);
+        self
+    }
+
+    /// Limit lines to `max` Unicode scalar values once decoded, which formats
+    /// with a character cap count instead of bytes. Longer lines are rejected
+    /// or cut after their `max`th scalar, never in the middle of one, as
+    /// `overflow` says. The limit applies after case folding and before
+    /// padding.
+    pub fn max_chars(mut self, max: usize, overflow: CharOverflow) -> Self {
+        self.line_options.max_chars = Some((max, overflow));
+        self
+    }
+
+    /// Remove a single space right before a CRLF terminator, as some Windows
+    /// tools write `" \r\n"` line endings. Other trailing whitespace, and
+    /// spaces before a bare LF, are kept. Defaults to `false`.
+    pub fn strip_space_before_crlf(mut self, strip: bool) -> Self {
+        self.line_options.strip_space_before_crlf = strip;
+        self
+    }
+
+    /// Remove a single `\r` left at the end of a line once its terminator is
+    /// removed, like the `\r` of a last line without a following `\n`, or
+    /// the first `\r` of a `\r\r\n` ending. CRLF terminators are removed
+    /// either way. Defaults to `false`.
+    pub fn strip_lone_cr(mut self, strip: bool) -> Self {
+        self.line_options.strip_lone_cr = strip;
+        self
+    }
+
+    /// Keep, remove or split lines on NUL bytes, which corrupted logs often
+    /// have embedded in their text. Defaults to [`NulPolicy::Keep`].
+    pub fn nul_policy(mut self, policy: NulPolicy) -> Self {
+        self.line_options.nul_policy = policy;
+        self
+    }
+
+    /// Don't end lines at delimiters right after an `escape` byte, for formats
+    /// where for example a backslash right before a new line character
+    /// continues the record. The escape byte and the escaped delimiter are
+    /// kept in the line. By default every delimiter ends a line.
+    pub fn escaped_delimiters(mut self, escape: u8) -> Self {
+        self.line_options.escape = Some(escape);
+        self
+    }
+
+    /// Leave out empty lines, like the blank separators between log entries.
+    /// By default empty lines are yielded like any other line. Skipped lines
+    /// are counted by [`RevLines::skipped_lines`]. Defaults to `false`.
+    pub fn skip_empty_lines(mut self, skip_empty: bool) -> Self {
+        self.line_options.skip_empty = skip_empty;
+        self
+    }
+
+    /// Leave out lines that are empty once trimmed of ASCII whitespace, see
+    /// [`RevLinesBuilder::skip_empty_lines`]. Defaults to `false`.
+    pub fn skip_blank_lines(mut self, skip_blank: bool) -> Self {
+        self.line_options.skip_blank = skip_blank;
+        self
+    }
+
+    /// Replace invalid UTF-8 sequences with U+FFFD like
+    /// `String::from_utf8_lossy`, instead of failing with
+    /// [`Error::NotUtf8`]. Defaults to `false`.
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.line_options.lossy = if lossy {
+            Some(char::REPLACEMENT_CHARACTER)
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Enable lossy decoding, see [`RevLinesBuilder::lossy`], replacing every
+    /// invalid UTF-8 sequence with `repl` instead of U+FFFD, for example with
+    /// a visible `?` when sanitizing logs.
+    pub fn with_lossy_replacement(mut self, repl: char) -> Self {
+        self.line_options.lossy = Some(repl);
+        self
+    }
+
+    /// Convert every line to lowercase, for case-insensitive matching.
+    /// Defaults to `false`. See [`unicode_case`](Self::unicode_case).
+    pub fn to_lowercase(mut self, to_lowercase: bool) -> Self {
+        self.set_case(Case::Lower, to_lowercase);
+        self
+    }
+
+    /// Convert every line to uppercase, for case-insensitive matching.
+    /// Defaults to `false`. See [`unicode_case`](Self::unicode_case).
+    pub fn to_uppercase(mut self, to_uppercase: bool) -> Self {
+        self.set_case(Case::Upper, to_uppercase);
+        self
+    }
+
+    /// Fold case with the full Unicode mappings of `str::to_lowercase` and
+    /// `str::to_uppercase` instead of only the ASCII letters. ASCII folding
+    /// is done on the raw bytes and is faster, but leaves letters like `Ä`
+    /// untouched. Defaults to `false`.
+    pub fn unicode_case(mut self, unicode_case: bool) -> Self {
+        self.line_options.unicode_case = unicode_case;
+        self
+    }
+
+    /// Normalize every line to `form` once it is decoded, so canonically
+    /// equivalent lines, like `é` written precomposed or with a combining
+    /// accent, compare equal in dedup and matching. Applies after case
+    /// folding, and before [`RevLinesBuilder::max_chars`] counts characters.
+    /// Byte-level options and adapters that see the raw bytes aren't
+    /// affected.
+    #[cfg(feature = "unicode-normalization")]
+    pub fn normalize(mut self, form: Normalization) -> Self {
+        self.line_options.normalization = Some(form);
+        self
+    }
+
+    /// Pad every line with spaces up to `width` columns, for columnar
+    /// display. Width is counted with `unicode-width`, so wide characters
+    /// like CJK take two columns and combining marks none. Lines that are
+    /// already at least `width` columns wide are left as they are.
+    #[cfg(feature = "unicode-width")]
+    pub fn pad_to(mut self, width: usize, pad: Pad) -> Self {
+        self.line_options.padding = Some((width, pad));
+        self
+    }
+
+    /// Pick the capacity, [`RevLinesBuilder::yield_interval`] and
+    /// [`RevLinesBuilder::whole_read_threshold`] for a trade-off between
+    /// latency and throughput in one go, as the variants of [`Profile`]
+    /// describe. Settings made after the profile override it.
+    pub fn profile(mut self, profile: Profile) -> Self {
+        let (capacity, yield_interval, whole_read_threshold) = match profile {
+            Profile::LowLatency => (1024, 16, None),
+            Profile::Balanced => (8 * 1024, 256, Some(64 * 1024)),
+            Profile::HighThroughput => (64 * 1024, 0, Some(1024 * 1024)),
+        };
+
+        self.whole_read_threshold = whole_read_threshold;
+        self.capacity(capacity).yield_interval(yield_interval)
+    }
+
+    fn set_case(&mut self, case: Case, enabled: bool) {
+        if enabled {
+            self.line_options.case = Some(case);
+        } else if self.line_options.case == Some(case) {
+            self.line_options.case = None;
+        }
+    }
+
+    /// End the stream at the next poll once `token` is cancelled, even while
+    /// a read is still in flight.
+    #[cfg(feature = "tokio-util")]
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Once `deadline` has passed, yield [`Error::DeadlineExceeded`] and end
+    /// the stream, no matter how far the read has got. The deadline is
+    /// checked whenever the stream is polled, and the stream is woken up
+    /// when it passes.
+    #[cfg(feature = "time")]
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Retry reads and seeks that fail with a transient error according to
+    /// `policy`, for flaky network-backed readers. A retried seek is started
+    /// over, so it must not have moved the reader when it failed. The seek to
+    /// the end of the reader when the stream is created isn't retried.
+    #[cfg(feature = "time")]
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Keep the line being assembled compressed with zstd in parts of
+    /// `threshold` bytes, and only decompress it once it is complete. Lowers
+    /// the memory held for huge lines with repetitive content, like a file of
+    /// one giant line, at the cost of compressing every part. Shorter lines
+    /// are never compressed. By default nothing is compressed.
+    #[cfg(feature = "compress-lines")]
+    pub fn compress_lines_over(mut self, threshold: usize) -> Self {
+        self.compress_threshold = Some(threshold);
+        self
+    }
+
+    /// Create the async stream of strings from a `BufReader<R>`.
+    pub async fn build<R: AsyncSeek + AsyncRead + Unpin>(
+        mut self,
+        mut reader: BufReader<R>,
+    ) -> Result<RevLines<R>, Error> {
+        let len = if let Some(len) = self.known_length {
+            let len = self.without_trailer(len)?;
+            reader.seek(SeekFrom::Start(len)).await?;
+            len
+        } else {
+            // Seek to end of reader now
+            let reader_size = match reader.seek(SeekFrom::End(0)).await {
+                Ok(reader_size) => reader_size,
+                Err(_) if self.fallback_length => copy(&mut reader, &mut sink()).await?,
+                Err(e) => return Err(Error::Io(e)),
+            };
+
+            if self.trailer_len > 0 {
+                let text_len = self.without_trailer(reader_size)?;
+                reader.seek(SeekFrom::Start(text_len)).await?;
+                text_len
+            } else {
+                reader_size
+            }
+        };
+
+        if self.seek_strategy == SeekStrategy::Auto {
+            self.seek_strategy = probe_seeks(&mut reader, len, std::time::Instant::now).await?;
+        }
+
+        self.build_with_size(reader, len)
+    }
+
+    /// The length of the text before the trailer of a reader of `len` bytes.
+    fn without_trailer(&self, len: u64) -> Result<u64, Error> {
+        len.checked_sub(self.trailer_len).ok_or_else(|| {
+            Error::Io(tokio::io::Error::new(
+                tokio::io::ErrorKind::InvalidInput,
+                "trailer is longer than the reader",
+            ))
+        })
+    }
+
+    /// The size of the chunks that are read.
+    fn buf_size(&self) -> u64 {
+        // Chunk sizes are also seek offsets, which have to fit in an i64
+        self.capacity.clamp(1, i64::MAX as usize) as u64
+    }
+
+    /// The size of the chunks read from a reader of `reader_size` bytes,
+    /// which is the whole reader if it is under the whole read threshold.
+    fn chunk_size(&self, reader_size: u64) -> u64 {
+        match self.whole_read_threshold {
+            Some(threshold) if reader_size <= threshold => max(self.buf_size(), reader_size),
+            _ => self.buf_size(),
+        }
+    }
+
+    /// Create the stream for the first `reader_size` bytes of `reader`.
+    fn build_with_size<R: AsyncSeek + AsyncRead + Unpin>(
+        self,
+        reader: BufReader<R>,
+        reader_size: u64,
+    ) -> Result<RevLines<R>, Error> {
+        if let Some(write_pos) = self.ring_start {
+            if write_pos > reader_size {
+                let e = tokio::io::Error::new(
+                    tokio::io::ErrorKind::InvalidInput,
+                    "ring write position is past the end of the reader",
+                );
+                return Err(Error::Io(e));
+            }
+        }
+
+        let stop_pos = min(self.stop_pos, reader_size);
+
+        Ok(RevLines {
+            reader,
+            reader_pos: reader_size,
+            reader_len: reader_size,
+            stop_pos,
+            buf_size: self.chunk_size(reader_size),
+            absolute_seeks: self.seek_strategy == SeekStrategy::Absolute
+                || self.ring_start.is_some(),
+            ring_start: self.ring_start,
+            yield_interval: self.yield_interval,
+            lines_since_yield: 0,
+            max_lookahead: self.max_lookahead,
+            heartbeat_interval: 0,
+            heartbeat_pos: reader_size,
+            heartbeat: None,
+            strict: self.strict,
+            error_mode: self.error_mode,
+            errors: Vec::new(),
+            match_needle: self.match_needle,
+            match_count: 0,
+            line_options: self.line_options,
+            #[cfg(feature = "tokio-util")]
+            cancelled: self
+                .cancellation
+                .map(|token| Box::pin(token.cancelled_owned())),
+            #[cfg(feature = "time")]
+            deadline: self
+                .deadline
+                .map(|deadline| Box::pin(sleep_until(deadline))),
+            #[cfg(feature = "time")]
+            retry: self.retry,
+            #[cfg(feature = "time")]
+            retries: 0,
+            #[cfg(feature = "time")]
+            backoff: None,
+            read_state: ReadState::Idle,
+            buf: Vec::new(),
+            line: Vec::new(),
+            at_eof: true,
+            terminated: false,
+            line_ready: false,
+            line_start: 0,
+            line_end: 0,
+            terminator_len: 0,
+            #[cfg(feature = "debug-chunks")]
+            chunks_read: 0,
+            #[cfg(feature = "debug-chunks")]
+            chunk_log: Vec::new(),
+            #[cfg(feature = "compress-lines")]
+            compress_threshold: self.compress_threshold,
+            #[cfg(feature = "compress-lines")]
+            compressed_line: Vec::new(),
+            #[cfg(feature = "compress-lines")]
+            compressed_len: 0,
+            #[cfg(test)]
+            ascii_lines: 0,
+            line_oversized: false,
+            pending_delimiter: None,
+            skipped_lines: 0,
+            reached_start: reader_size == stop_pos,
+            on_start_reached: None,
+            on_line_progress: None,
+            line_progress: false,
+            done: reader_size == stop_pos,
+        })
+    }
+}
+
+/// Progress of the chunk read that is currently in flight.
+enum ReadState {
+    Idle,
+    Seeking { size: u64, started: bool },
+    Reading { filled: usize },
+    Rewinding { size: u64, started: bool },
+}
+
+/// An async stream of the lines of a `BufReader<R>`, last line first.
+///
+/// `R` may borrow its data, for example a `Cursor<&mut [u8]>` over a buffer
+/// the caller keeps using once the stream is dropped. The data is never
+/// written to.
+///
+/// Every seek goes through the `BufReader`, never the inner reader, since
+/// seeking the `BufReader` discards the bytes it has buffered. Bytes
+/// buffered before the stream was created, or ahead of a chunk read, are
+/// therefore never mistaken for the bytes at the new position.
+///
+/// Chunks are read from the inner reader, right after the seek emptied the
+/// buffer of the `BufReader`, so the reads the inner reader sees only depend
+/// on the reader and the capacity of the stream, not on the capacity of the
+/// `BufReader`.
+pub struct RevLines<R> {
+    reader: BufReader<R>,
+    // Start of the region that has been read. Everything before it is unread.
+    reader_pos: u64,
+    reader_len: u64,
+    // Where reading stops, at the start of the first line of the stream
+    stop_pos: u64,
+    buf_size: u64,
+    absolute_seeks: bool,
+    // Offset of the oldest byte when the reader is a ring buffer. Offsets
+    // like `reader_pos` then count from there instead of from the start.
+    ring_start: Option<u64>,
+    // Lines to hand out between giving other tasks a chance to run
+    yield_interval: usize,
+    lines_since_yield: usize,
+    // Most lines adapters may hold ahead of the lines they yield
+    max_lookahead: Option<usize>,
+    // Bytes to read without a line between heartbeats, the value of
+    // `reader_pos` at the last line or heartbeat, and a heartbeat to hand out
+    heartbeat_interval: u64,
+    heartbeat_pos: u64,
+    heartbeat: Option<u64>,
+    strict: bool,
+    error_mode: ErrorMode,
+    // Errors kept out of the stream by `ErrorMode::Collect`
+    errors: Vec<Error>,
+    // Substring to count the lines with, and their count
+    match_needle: Option<Vec<u8>>,
+    match_count: u64,
+    line_options: LineOptions,
+    #[cfg(feature = "tokio-util")]
+    cancelled: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+    #[cfg(feature = "time")]
+    deadline: Option<Pin<Box<Sleep>>>,
+    #[cfg(feature = "time")]
+    retry: Option<RetryPolicy>,
+    // Retries of the current chunk read so far, and the wait before the next
+    #[cfg(feature = "time")]
+    retries: u32,
+    #[cfg(feature = "time")]
+    backoff: Option<Pin<Box<Sleep>>>,
+    read_state: ReadState,
+    // Read bytes that have not been assigned to a line yet. They cover
+    // `reader_pos..reader_pos + buf.len()`.
+    buf: Vec<u8>,
+    // Bytes of the line being assembled, written backwards.
+    line: Vec<u8>,
+    // Nothing has been scanned yet, so the next byte is the last one of the reader.
+    at_eof: bool,
+    // Whether the line being assembled is followed by a new line character.
+    terminated: bool,
+    // `line` holds a complete line that has been handed out.
+    line_ready: bool,
+    // Offset of the first byte of the last complete line.
+    line_start: u64,
+    // Offset right after its content, and the length of its terminator.
+    line_end: u64,
+    terminator_len: u64,
+    // Chunks read so far, and the logical ranges of those that may still
+    // hold bytes of lines that haven't been handed out.
+    #[cfg(feature = "debug-chunks")]
+    chunks_read: u64,
+    #[cfg(feature = "debug-chunks")]
+    chunk_log: Vec<(u64, Range<u64>)>,
+    // Compressed parts of the line being assembled that come before `line`,
+    // with their lengths, and their total length
+    #[cfg(feature = "compress-lines")]
+    compress_threshold: Option<usize>,
+    #[cfg(feature = "compress-lines")]
+    compressed_line: Vec<(Vec<u8>, usize)>,
+    #[cfg(feature = "compress-lines")]
+    compressed_len: usize,
+    // Lines decoded without validation, since they were all ASCII
+    #[cfg(test)]
+    ascii_lines: usize,
+    // The line being assembled is too long to be yielded.
+    line_oversized: bool,
+    // A delimiter right before the line being assembled, which may still be
+    // escaped by the last byte before it that isn't read yet.
+    pending_delimiter: Option<u8>,
+    skipped_lines: u64,
+    // The first byte of the reader has been scanned.
+    reached_start: bool,
+    on_start_reached: Option<Box<dyn FnOnce() + Send>>,
+    // Called with the bytes left to scan while a line spans chunks, and
+    // whether it was called for the line being assembled
+    on_line_progress: Option<Box<dyn FnMut(u64) + Send>>,
+    line_progress: bool,
+    done: bool,
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> RevLines<R> {
+    /// Create an async stream of strings from a `BufReader<R>`. Internal
+    /// buffering for iteration will default to 4096 bytes at a time.
+    pub async fn new(reader: BufReader<R>) -> Result<RevLines<R>, Error> {
+        RevLines::with_capacity(DEFAULT_SIZE, reader).await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>`. Internal
+    /// buffering for iteration will use `cap` bytes at a time.
+    pub async fn with_capacity(cap: usize, reader: BufReader<R>) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new().capacity(cap).build(reader).await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` that ends
+    /// cleanly once `token` is cancelled.
+    #[cfg(feature = "tokio-util")]
+    pub async fn with_cancellation(
+        token: CancellationToken,
+        reader: BufReader<R>,
+    ) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new()
+            .cancellation(token)
+            .build(reader)
+            .await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` that ends with
+    /// [`Error::DeadlineExceeded`] once `deadline` has passed.
+    #[cfg(feature = "time")]
+    pub async fn with_deadline(
+        deadline: Instant,
+        reader: BufReader<R>,
+    ) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new()
+            .deadline(deadline)
+            .build(reader)
+            .await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` that retries
+    /// reads and seeks failing with transient errors. See
+    /// [`RevLinesBuilder::retry`].
+    #[cfg(feature = "time")]
+    pub async fn with_retry(
+        policy: RetryPolicy,
+        reader: BufReader<R>,
+    ) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new().retry(policy).build(reader).await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` of `len`
+    /// bytes, without seeking to its end. See
+    /// [`RevLinesBuilder::known_length`].
+    pub async fn with_known_length(len: u64, reader: BufReader<R>) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new().known_length(len).build(reader).await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` without its
+    /// last `n` bytes. See [`RevLinesBuilder::trailer_bytes`].
+    pub async fn with_trailer_bytes(n: u64, reader: BufReader<R>) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new().trailer_bytes(n).build(reader).await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` tuned for
+    /// `profile`. See [`RevLinesBuilder::profile`].
+    pub async fn with_profile(
+        profile: Profile,
+        reader: BufReader<R>,
+    ) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new().profile(profile).build(reader).await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` that lets
+    /// other tasks run after every `k` lines. See
+    /// [`RevLinesBuilder::yield_interval`].
+    pub async fn with_yield_interval(k: usize, reader: BufReader<R>) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new().yield_interval(k).build(reader).await
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` that is a
+    /// ring buffer with its next write going to `write_pos`. See
+    /// [`RevLinesBuilder::ring`].
+    pub async fn with_ring(write_pos: u64, reader: BufReader<R>) -> Result<RevLines<R>, Error> {
+        RevLinesBuilder::new().ring(write_pos).build(reader).await
+    }
+
+    /// Create an async stream of the lines of the part of a `BufReader<R>`
+    /// that starts at `pct` of its length, like `0.9` for the last 10%. The
+    /// offset at `pct` is moved forward to the start of the next line, so
+    /// the last line yielded is a whole one; the offset is kept if a line
+    /// starts right there. Fails with `ErrorKind::InvalidInput` unless `pct`
+    /// is between 0 and 1. See [`RevLinesBuilder::stop_at`].
+    pub async fn from_percentage(pct: f64, mut reader: BufReader<R>) -> Result<RevLines<R>, Error> {
+        if !(0.0..=1.0).contains(&pct) {
+            let e = tokio::io::Error::new(
+                tokio::io::ErrorKind::InvalidInput,
+                "percentage is not between 0 and 1",
+            );
+            return Err(Error::Io(e));
+        }
+
+        let len = reader.seek(SeekFrom::End(0)).await?;
+        let offset = min((len as f64 * pct) as u64, len);
+
+        // Find the line start at or after `offset` from the byte before it
+        let mut stop_pos = offset;
+        if offset > 0 {
+            reader.seek(SeekFrom::Start(offset - 1)).await?;
+            let mut skipped = Vec::new();
+            reader.read_until(LF_BYTE, &mut skipped).await?;
+
+            stop_pos = if skipped.last() == Some(&LF_BYTE) {
+                offset - 1 + skipped.len() as u64
+            } else {
+                len
+            };
+        }
+
+        RevLinesBuilder::new().stop_at(stop_pos).build(reader).await
+    }
+
+    /// The length of the reader in bytes, as found when the stream was
+    /// created.
+    pub fn len(&self) -> u64 {
+        self.reader_len
+    }
+
+    /// Whether the reader was empty when the stream was created.
+    pub fn is_empty(&self) -> bool {
+        self.reader_len == 0
+    }
+
+    /// The number of lines that have been left out of the stream, see
+    /// [`RevLinesBuilder::skip_lines_longer_than`] and
+    /// [`RevLinesBuilder::skip_empty_lines`].
+    pub fn skipped_lines(&self) -> u64 {
+        self.skipped_lines
+    }
+
+    /// The number of lines read so far that contain the substring of
+    /// [`RevLinesBuilder::count_matches`], including lines that then fail to
+    /// decode. Always 0 if no substring was given.
+    pub fn match_count(&self) -> u64 {
+        self.match_count
+    }
+
+    /// The errors of the lines that were kept out of the stream so far, in
+    /// the order they were read. Only [`ErrorMode::Collect`] collects errors.
+    pub fn errors(&self) -> &[Error] {
+        &self.errors
+    }
+
+    /// Whether the start of the range being read has been reached, so that
+    /// the line yielded last is the first line of the range. The range
+    /// starts at offset 0 of the reader, or where
+    /// [`RevLinesBuilder::stop_at`] or [`RevLines::from_percentage`] put it.
+    pub fn reached_start(&self) -> bool {
+        self.reached_start
+    }
+
+    /// Call `f` once the whole range being read has been read, when the
+    /// stream ends after yielding the first line of the range, see
+    /// [`RevLines::reached_start`]. Not called if the stream ends early, on
+    /// an I/O error, cancellation, or deadline.
+    pub fn on_start_reached<F>(&mut self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.on_start_reached = Some(Box::new(f));
+    }
+
+    /// Call `f` with the number of bytes left to scan for the start of the
+    /// line being assembled, after every chunk that didn't contain it, for a
+    /// determinate progress bar on one huge line. The count is the bytes of
+    /// the reader before the chunk, the most the line may still need, and
+    /// `f` is called with 0 once the start is found. Lines that start in the
+    /// chunk their end was found in report no progress.
+    pub fn on_line_progress<F>(&mut self, f: F)
+    where
+        F: FnMut(u64) + Send + 'static,
+    {
+        self.on_line_progress = Some(Box::new(f));
+    }
+
+    /// Report the progress of the line being assembled after a scan of
+    /// `buf`, which found the start of the line if `found` is set.
+    fn report_line_progress(&mut self, found: bool) {
+        let f = match &mut self.on_line_progress {
+            Some(f) => f,
+            None => return,
+        };
+
+        if found || self.reader_pos == self.stop_pos {
+            if std::mem::take(&mut self.line_progress) {
+                f(0);
+            }
+        } else {
+            f(self.reader_pos - self.stop_pos);
+            self.line_progress = true;
+        }
+    }
+
+    /// Read the chunk of bytes that ends at `reader_pos` into `buf`.
+    fn poll_read_to_buffer(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        #[cfg(feature = "time")]
+        let result = ready!(self.poll_read_retrying(cx));
+        #[cfg(not(feature = "time"))]
+        let result = ready!(self.poll_read_steps(cx));
+        self.read_state = ReadState::Idle;
+
+        #[cfg(feature = "debug-chunks")]
+        if result.is_ok() {
+            let chunk = self.reader_pos..self.reader_pos + self.buf.len() as u64;
+            self.chunk_log.push((self.chunks_read, chunk));
+            self.chunks_read += 1;
+        }
+
+        Poll::Ready(result)
+    }
+
+    /// Like `poll_read_steps`, retrying as the retry policy allows.
+    #[cfg(feature = "time")]
+    fn poll_read_retrying(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        loop {
+            if let Some(backoff) = &mut self.backoff {
+                ready!(backoff.as_mut().poll(cx));
+                self.backoff = None;
+            }
+
+            let result = ready!(self.poll_read_steps(cx));
+            if let Err(Error::Io(e)) = &result {
+                if self.start_retry(e.kind()) {
+                    continue;
+                }
+            }
+
+            self.retries = 0;
+            return Poll::Ready(result);
+        }
+    }
+
+    /// Schedule another attempt at the failed step of the chunk read, if the
+    /// retry policy allows one for an error of `kind`.
+    #[cfg(feature = "time")]
+    fn start_retry(&mut self, kind: tokio::io::ErrorKind) -> bool {
+        use tokio::io::ErrorKind::{Interrupted, TimedOut, WouldBlock};
+
+        let policy = match self.retry {
+            Some(policy) => policy,
+            None => return false,
+        };
+        if !matches!(kind, WouldBlock | TimedOut | Interrupted)
+            || self.retries >= policy.max_retries
+        {
+            return false;
+        }
+
+        match &mut self.read_state {
+            ReadState::Seeking { started, .. } | ReadState::Rewinding { started, .. } => {
+                *started = false;
+            }
+            ReadState::Idle | ReadState::Reading { .. } => {}
+        }
+
+        self.backoff = Some(Box::pin(sleep(policy.delay(self.retries))));
+        self.retries += 1;
+        true
+    }
+
+    fn poll_read_steps(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        loop {
+            match &mut self.read_state {
+                ReadState::Idle => {
+                    // Read the of minimum between the desired
+                    // buffer size or remaining length of the reader
+                    let mut size = min(self.buf_size, self.reader_pos - self.stop_pos);
+
+                    // A chunk of a ring buffer must not span its wrap
+                    if let Some(ring_start) = self.ring_start {
+                        let wrap = self.reader_len - ring_start;
+                        if self.reader_pos > wrap {
+                            size = min(size, self.reader_pos - wrap);
+                        }
+                    }
+
+                    self.buf.resize(size as usize, 0);
+                    self.read_state = ReadState::Seeking {
+                        size,
+                        started: false,
+                    };
+                }
+
+                ReadState::Seeking { size, started } => {
+                    let size = *size;
+                    let expected =
+                        physical_pos(self.ring_start, self.reader_len, self.reader_pos - size);
+                    let pos = if self.absolute_seeks {
+                        SeekFrom::Start(expected)
+                    } else {
+                        SeekFrom::Current(-(size as i64))
+                    };
+
+                    let actual = ready!(poll_seek(Pin::new(&mut self.reader), cx, pos, started))?;
+                    self.check_position(actual, expected)?;
+                    self.read_state = ReadState::Reading { filled: 0 };
+                }
+
+                ReadState::Reading { filled } => {
+                    let mut read_buf = ReadBuf::new(&mut self.buf);
+                    read_buf.set_filled(*filled);
+                    let result = Pin::new(self.reader.get_mut()).poll_read(cx, &mut read_buf);
+                    let now_filled = read_buf.filled().len();
+
+                    ready!(result)?;
+                    if now_filled == *filled {
+                        // The reader got shorter since its length was taken
+                        let offset = self.reader_pos - self.buf.len() as u64 + now_filled as u64;
+                        return Poll::Ready(Err(Error::Shrunk {
+                            offset,
+                            len: self.reader_len,
+                        }));
+                    }
+                    *filled = now_filled;
+
+                    if now_filled == self.buf.len() {
+                        let size = now_filled as u64;
+
+                        // Absolute seeks never rely on the reader's current position
+                        if self.absolute_seeks {
+                            self.reader_pos -= size;
+                            return Poll::Ready(Ok(()));
+                        }
+
+                        self.read_state = ReadState::Rewinding {
+                            size,
+                            started: false,
+                        };
+                    }
+                }
+
+                ReadState::Rewinding { size, started } => {
+                    let size = *size;
+                    let pos = SeekFrom::Current(-(size as i64));
+
+                    let actual = ready!(poll_seek(Pin::new(&mut self.reader), cx, pos, started))?;
+                    self.reader_pos -= size;
+                    self.check_position(actual, self.reader_pos)?;
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+
+    /// In strict mode, make sure a seek landed where it was meant to.
+    fn check_position(&self, actual: u64, expected: u64) -> Result<(), Error> {
+        if self.strict && actual != expected {
+            return Err(Error::InconsistentSeek { expected, actual });
+        }
+
+        Ok(())
+    }
+
+    /// Move the bytes after the last new line character in `buf` into the
+    /// line being assembled. Returns `true` if a new line character was found,
+    /// which means the line is complete.
+    fn scan_buffer(&mut self) -> bool {
+        // Handle a trailing new line character of the reader
+        // so the first line yielded is not an empty one
+        if self.at_eof && !self.buf.is_empty() {
+            self.at_eof = false;
+
+            if self.buf.last() == Some(&LF_BYTE) {
+                self.buf.pop();
+                self.terminated = true;
+            }
+        }
+
+        let escape = self.line_options.escape;
+
+        if let Some(delimiter) = self.pending_delimiter {
+            match self.buf.last() {
+                Some(ch) if Some(*ch) == escape => {
+                    self.pending_delimiter = None;
+                    self.line.push(delimiter);
+                }
+                Some(_) => {
+                    self.pending_delimiter = None;
+                    return true;
+                }
+                // No bytes before the delimiter, so nothing escapes it
+                None if self.reader_pos == self.stop_pos => {
+                    self.pending_delimiter = None;
+                    return true;
+                }
+                None => return false,
+            }
+        }
+
+        let split_on_nul = self.line_options.nul_policy == NulPolicy::SplitOnNul;
+        let mut end = self.buf.len();
+        loop {
+            match self.buf[..end]
+                .iter()
+                .rposition(|ch| *ch == LF_BYTE || (split_on_nul && *ch == 0))
+            {
+                Some(idx) if idx > 0 && Some(self.buf[idx - 1]) == escape => {
+                    end = idx;
+                }
+                // The byte that may escape the delimiter is in the next chunk
+                Some(0) if escape.is_some() && self.reader_pos > self.stop_pos => {
+                    self.pending_delimiter = Some(self.buf[0]);
+                    self.push_to_line(1);
+                    self.buf.clear();
+                    return false;
+                }
+                Some(idx) => {
+                    self.push_to_line(idx + 1);
+                    self.buf.truncate(idx);
+                    return true;
+                }
+
+                None => {
+                    self.push_to_line(0);
+                    self.buf.clear();
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Add `buf[from..]` to the front of the line being assembled.
+    fn push_to_line(&mut self, from: usize) {
+        let bytes = &self.buf[from..];
+
+        // Lines that are going to be skipped for their length aren't kept
+        // around. One extra byte is kept for the carriage return of CRLF.
+        if let Some(max_len) = self.line_options.max_line_len {
+            if self.line_oversized || self.assembled_len() + bytes.len() > max_len + 1 {
+                self.line_oversized = true;
+                return;
+            }
+        }
+
+        self.line.extend(bytes.iter().rev());
+
+        #[cfg(feature = "compress-lines")]
+        if let Some(threshold) = self.compress_threshold {
+            if self.line.len() >= threshold {
+                let frame = zstd::bulk::compress(&self.line, 0);
+                // Keep the bytes as they are if they can't be compressed
+                if let Ok(mut frame) = frame {
+                    frame.shrink_to_fit();
+                    self.compressed_line.push((frame, self.line.len()));
+                    self.compressed_len += self.line.len();
+                    self.line.clear();
+                }
+            }
+        }
+    }
+
+    /// The bytes allocated for the line being assembled, compressed or not.
+    #[cfg(all(test, feature = "compress-lines"))]
+    fn held_line_bytes(&self) -> usize {
+        self.line.capacity()
+            + self
+                .compressed_line
+                .iter()
+                .map(|(frame, _)| frame.capacity())
+                .sum::<usize>()
+    }
+
+    /// The number of bytes of the line being assembled so far.
+    fn assembled_len(&self) -> usize {
+        #[cfg(feature = "compress-lines")]
+        let len = self.compressed_len + self.line.len();
+        #[cfg(not(feature = "compress-lines"))]
+        let len = self.line.len();
+
+        len
+    }
+
+    /// Put the compressed parts of the line being assembled back in front of
+    /// the bytes in `line`.
+    #[cfg(feature = "compress-lines")]
+    fn decompress_line(&mut self) -> Result<(), Error> {
+        if self.compressed_line.is_empty() {
+            return Ok(());
+        }
+
+        let mut line = Vec::with_capacity(self.compressed_len + self.line.len());
+        for (frame, len) in std::mem::take(&mut self.compressed_line) {
+            line.extend(zstd::bulk::decompress(&frame, len)?);
+        }
+        line.append(&mut self.line);
+
+        self.line = line;
+        self.compressed_len = 0;
+        Ok(())
+    }
+
+    /// Whether the finished line is left out of the stream.
+    fn skip_line(&mut self) -> bool {
+        let oversized = std::mem::take(&mut self.line_oversized);
+
+        let too_long = match self.line_options.max_line_len {
+            Some(max_len) => oversized || self.line.len() > max_len,
+            None => false,
+        };
+        let empty = (self.line_options.skip_empty && self.line.is_empty())
+            || (self.line_options.skip_blank && self.line.iter().all(u8::is_ascii_whitespace));
+
+        if too_long || empty {
+            self.skipped_lines += 1;
+            return true;
+        }
+
+        false
+    }
+
+    /// Put the assembled line, which starts at offset `start`, in the right
+    /// order, without the carriage return of a CRLF terminator.
+    fn finish_line(&mut self, start: u64) {
+        self.line_start = start;
+
+        // Reverse the results since they were written backwards
+        self.line.reverse();
+
+        self.terminator_len = 0;
+        if self.terminated {
+            self.terminator_len = 1;
+            if self.line.last() == Some(&CR_BYTE) {
+                self.line.pop();
+                self.terminator_len = 2;
+            }
+        }
+        self.line_end = start + self.line.len() as u64;
+
+        if self.line_options.strip_space_before_crlf
+            && self.terminator_len == 2
+            && self.line.last() == Some(&b' ')
+        {
+            self.line.pop();
+        }
+
+        if self.line_options.strip_lone_cr && self.line.last() == Some(&CR_BYTE) {
+            self.line.pop();
+        }
+
+        if self.line_options.nul_policy == NulPolicy::StripNuls {
+            self.line.retain(|ch| *ch != 0);
+        }
+
+        if !self.line_options.unicode_case {
+            match self.line_options.case {
+                Some(Case::Lower) => self.line.make_ascii_lowercase(),
+                Some(Case::Upper) => self.line.make_ascii_uppercase(),
+                None => {}
+            }
+        }
+
+        // Every line before this one is followed by a new line character
+        self.terminated = true;
+        self.line_ready = true;
+    }
+
+    /// Byte range of the last complete line in the reader.
+    fn line_span(&self, include_terminator: bool) -> Range<u64> {
+        if include_terminator {
+            self.line_start..self.line_end + self.terminator_len
+        } else {
+            self.line_start..self.line_end
+        }
+    }
+
+    /// The chunks the last complete line and its terminator were read from,
+    /// in the order of their offsets.
+    #[cfg(feature = "debug-chunks")]
+    fn line_chunks(&self) -> Vec<LineChunk> {
+        let span = self.line_span(true);
+
+        let mut chunks: Vec<_> = self
+            .chunk_log
+            .iter()
+            .filter(|(_, chunk)| chunk.start < span.end && span.start < chunk.end)
+            .map(|(index, chunk)| {
+                let start = span.start.max(chunk.start) - chunk.start;
+                let end = span.end.min(chunk.end) - chunk.start;
+                LineChunk {
+                    index: *index,
+                    range: start as usize..end as usize,
+                }
+            })
+            .collect();
+        chunks.reverse();
+
+        chunks
+    }
+
+    /// Take the finished line out of `line` and convert it to a String.
+    fn decode_line(&mut self) -> Result<String, Error> {
+        // Convert to a String. Lines of only ASCII are valid UTF-8 as they
+        // are, other lines go through full validation.
+        let bytes = std::mem::take(&mut self.line);
+        let decoded = if bytes.is_ascii() {
+            #[cfg(test)]
+            {
+                self.ascii_lines += 1;
+            }
+
+            // SAFETY: every ASCII byte is a UTF-8 character on its own
+            Ok(unsafe { String::from_utf8_unchecked(bytes) })
+        } else {
+            String::from_utf8(bytes)
+        };
+
+        self.process_decoded(decoded)
+    }
+
+    /// Convert the finished line to a String like `decode_line`, but leave
+    /// its bytes in `line`.
+    fn decode_line_copy(&self) -> Result<String, Error> {
+        let decoded = match std::str::from_utf8(&self.line) {
+            Ok(line) => Ok(line.to_owned()),
+            Err(_) => String::from_utf8(self.line.clone()),
+        };
+
+        self.process_decoded(decoded)
+    }
+
+    /// Handle invalid UTF-8 in a decoded line and apply the options that
+    /// work on strings.
+    fn process_decoded(
+        &self,
+        decoded: Result<String, std::string::FromUtf8Error>,
+    ) -> Result<String, Error> {
+        let line = match decoded {
+            Ok(line) => line,
+            Err(e) => match self.line_options.lossy {
+                Some(repl) => replace_invalid(e.as_bytes(), repl),
+                None if self.strict => {
+                    let offset = self.line_start + e.utf8_error().valid_up_to() as u64;
+                    // An incomplete sequence at the very end of the reader
+                    let truncated = e.utf8_error().error_len().is_none()
+                        && self.terminator_len == 0
+                        && self.line_end == self.reader_len;
+
+                    if truncated {
+                        return Err(Error::TruncatedUtf8 { offset, source: e });
+                    }
+                    return Err(Error::InvalidUtf8 { offset, source: e });
+                }
+                None => return Err(e.into()),
+            },
+        };
+
+        let line = match self.line_options.case {
+            Some(Case::Lower) if self.line_options.unicode_case => line.to_lowercase(),
+            Some(Case::Upper) if self.line_options.unicode_case => line.to_uppercase(),
+            _ => line,
+        };
+
+        #[cfg(feature = "unicode-normalization")]
+        let line = {
+            use unicode_normalization::UnicodeNormalization;
+
+            match self.line_options.normalization {
+                Some(Normalization::Nfc) => line.nfc().collect(),
+                Some(Normalization::Nfd) => line.nfd().collect(),
+                None => line,
+            }
+        };
+
+        let line = match self.line_options.max_chars {
+            Some((max, overflow)) => match (line.char_indices().nth(max), overflow) {
+                (Some(_), CharOverflow::Reject) => {
+                    let offset = self.line_start;
+                    return Err(Error::TooManyChars { offset, max });
+                }
+                (Some((end, _)), CharOverflow::Truncate) => {
+                    let mut line = line;
+                    line.truncate(end);
+                    line
+                }
+                (None, _) => line,
+            },
+            None => line,
+        };
+
+        #[cfg(feature = "unicode-width")]
+        if let Some((width, pad)) = self.line_options.padding {
+            return Ok(pad_line(line, width, pad));
+        }
+
+        Ok(line)
+    }
+
+    /// Assemble the next line and turn it into an item with `decode`,
+    /// handling the errors of `decode` as the error mode says. Lines whose
+    /// errors are collected are skipped. Errors assembling a line always end
+    /// the stream.
+    fn poll_next_decoded<T, F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut decode: F,
+    ) -> Poll<Option<Result<T, Error>>>
+    where
+        F: FnMut(&mut Self) -> Result<T, Error>,
+    {
+        loop {
+            match ready!(self.poll_next_line(cx)) {
+                Some(Ok(())) => {}
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+
+            match (decode(self), self.error_mode) {
+                (Ok(item), _) => return Poll::Ready(Some(Ok(item))),
+                (Err(e), ErrorMode::Collect) => self.errors.push(e),
+                (Err(e), ErrorMode::Stop) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                (Err(e), ErrorMode::Yield) => return Poll::Ready(Some(Err(e))),
+            }
+        }
+    }
+
+    /// Assemble the next line into `line`. The line stays there until the
+    /// following call, so callers may either borrow or take it.
+    fn poll_next_line(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<(), Error>>> {
+        if self.line_ready {
+            self.line.clear();
+            self.line_ready = false;
+
+            // Only lines before the one handed out are left to be assembled
+            #[cfg(feature = "debug-chunks")]
+            {
+                let line_start = self.line_start;
+                self.chunk_log.retain(|(_, chunk)| chunk.start < line_start);
+            }
+        }
+
+        if self.yield_interval > 0 && self.lines_since_yield >= self.yield_interval {
+            self.lines_since_yield = 0;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        #[cfg(feature = "tokio-util")]
+        if let Some(cancelled) = &mut self.cancelled {
+            if cancelled.as_mut().poll(cx).is_ready() {
+                self.done = true;
+            }
+        }
+
+        #[cfg(feature = "time")]
+        if let Some(deadline) = &mut self.deadline {
+            if !self.done && deadline.as_mut().poll(cx).is_ready() {
+                self.done = true;
+                return Poll::Ready(Some(Err(Error::DeadlineExceeded)));
+            }
+        }
+
+        loop {
+            if self.done {
+                if self.reached_start {
+                    if let Some(f) = self.on_start_reached.take() {
+                        f();
+                    }
+                }
+
+                return Poll::Ready(None);
+            }
+
+            // `buf` is only complete once no read is in flight
+            if let ReadState::Idle = self.read_state {
+                let scanned = !self.buf.is_empty();
+                let found = self.scan_buffer();
+                if scanned {
+                    self.report_line_progress(found);
+                }
+
+                let line_start = if found {
+                    // The new line character is right after the unassigned bytes
+                    Some(self.reader_pos + self.buf.len() as u64 + 1)
+                } else if self.reader_pos == self.stop_pos {
+                    self.done = true;
+                    self.reached_start = true;
+                    Some(self.stop_pos)
+                } else {
+                    None
+                };
+
+                if let Some(line_start) = line_start {
+                    #[cfg(feature = "compress-lines")]
+                    if let Err(e) = self.decompress_line() {
+                        self.done = true;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    self.finish_line(line_start);
+
+                    if !self.skip_line() {
+                        if let Some(needle) = &self.match_needle {
+                            if contains_bytes(&self.line, needle) {
+                                self.match_count += 1;
+                            }
+                        }
+                        self.lines_since_yield += 1;
+                        self.heartbeat_pos = self.reader_pos;
+                        return Poll::Ready(Some(Ok(())));
+                    }
+
+                    self.line.clear();
+                    self.line_ready = false;
+                    continue;
+                }
+
+                // The line needs more bytes after a long stretch without one
+                if self.heartbeat_interval > 0
+                    && self.heartbeat_pos - self.reader_pos >= self.heartbeat_interval
+                {
+                    self.heartbeat_pos = self.reader_pos;
+                    self.heartbeat = Some(self.reader_len - self.reader_pos);
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+            }
+
+            if let Err(e) = ready!(self.poll_read_to_buffer(cx)) {
+                self.done = true;
+                return Poll::Ready(Some(Err(e)));
+            }
+        }
+    }
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin> Stream for RevLines<R> {
+    type Item = Result<String, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        this.poll_next_decoded(cx, Self::decode_line)
+    }
+}
+
+/// Time a few seeks of each kind that keep `reader` at `pos` with the clock
+/// `now`, and pick the faster kind for [`SeekStrategy::Auto`].
+async fn probe_seeks<R: AsyncSeek + AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+    pos: u64,
+    now: fn() -> std::time::Instant,
+) -> Result<SeekStrategy, Error> {
+    const PROBE_SEEKS: usize = 4;
+
+    let mut relative = Duration::ZERO;
+    let mut absolute = Duration::ZERO;
+    for _ in 0..PROBE_SEEKS {
+        let started = now();
+        reader.seek(SeekFrom::Current(0)).await?;
+        relative += now() - started;
+
+        let started = now();
+        reader.seek(SeekFrom::Start(pos)).await?;
+        absolute += now() - started;
+    }
+
+    if absolute < relative {
+        Ok(SeekStrategy::Absolute)
+    } else {
+        Ok(SeekStrategy::Relative)
+    }
+}
+
+/// Whether `needle` is found anywhere in `haystack`.
+fn contains_bytes(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window == needle)
+}
+
+/// Decode `bytes` with every maximal invalid UTF-8 sequence replaced by `repl`,
+/// splitting sequences the same way as `String::from_utf8_lossy`.
+fn replace_invalid(mut bytes: &[u8], repl: char) -> String {
+    let mut line = String::with_capacity(bytes.len());
+
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                line.push_str(valid);
+                return line;
+            }
+            Err(e) => {
+                let (valid, invalid) = bytes.split_at(e.valid_up_to());
+                line.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                line.push(repl);
+                bytes = &invalid[e.error_len().unwrap_or(invalid.len())..];
+            }
+        }
+    }
+}
+
+/// Add spaces to `line` until it is `width` columns wide.
+#[cfg(feature = "unicode-width")]
+fn pad_line(mut line: String, width: usize, pad: Pad) -> String {
+    let missing = width.saturating_sub(unicode_width::UnicodeWidthStr::width(line.as_str()));
+    let spaces = " ".repeat(missing);
+
+    match pad {
+        Pad::Left => line.insert_str(0, &spaces),
+        Pad::Right => line.push_str(&spaces),
+    }
+
+    line
+}
+
+/// Map an offset like `reader_pos` to an offset in a reader of `len` bytes.
+fn physical_pos(ring_start: Option<u64>, len: u64, pos: u64) -> u64 {
+    match ring_start {
+        Some(ring_start) if pos < len - ring_start => ring_start + pos,
+        Some(ring_start) => pos - (len - ring_start),
+        None => pos,
+    }
+}
+
+/// Drive a single seek of `seeker`. `started` tracks whether the seek was
+/// already handed over by an earlier poll.
+fn poll_seek<S: AsyncSeek + ?Sized>(
+    mut seeker: Pin<&mut S>,
+    cx: &mut Context<'_>,
+    pos: SeekFrom,
+    started: &mut bool,
+) -> Poll<tokio::io::Result<u64>> {
+    if !*started {
+        seeker.as_mut().start_seek(pos)?;
+        *started = true;
+    }
+
+    seeker.poll_complete(cx)
+}
+
+// Built with `--cfg loom`, tokio leaves out `fs` and only the models in `loom` run
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use futures_util::{pin_mut, StreamExt};
+    use std::future::Future;
+    use std::io::Cursor;
+    #[cfg(feature = "time")]
+    use std::time::Duration;
+    use tokio::fs::File;
+    use tokio::io::AsyncBufReadExt;
+
+    #[tokio::test]
+    async fn it_handles_empty_files() {
+        let file = File::open("tests/empty_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let results = vec![];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_handles_file_with_one_line() {
+        let file = File::open("tests/one_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let results = vec!["ABCD"];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_handles_file_with_multi_lines() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_handles_file_with_blank_lines() {
+        let file = File::open("tests/blank_line_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let results = vec!["", "", "XYZ", "", "ABCD"];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_strips_a_space_before_crlf() {
+        for cap in [1, 3, 4096].iter() {
+            let file = File::open("tests/crlf_space_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(*cap)
+                .strip_space_before_crlf(true)
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            let results = vec!["last ", "", "four", "three ", "two ", "one"];
+
+            assert_stream_eq(rev_lines, results).await;
+        }
+
+        let file = File::open("tests/crlf_space_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        let results = vec!["last ", " ", "four", "three ", "two  ", "one "];
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_strips_a_lone_cr_when_asked_to() {
+        for cap in [1, 3, 4096].iter() {
+            let file = File::open("tests/lone_cr_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(*cap)
+                .strip_lone_cr(true)
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            assert_stream_eq(rev_lines, vec!["gamma", "beta", "alpha"]).await;
+        }
+
+        let file = File::open("tests/lone_cr_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        assert_stream_eq(rev_lines, vec!["gamma\r", "beta", "alpha\r"]).await;
+    }
+
+    #[tokio::test]
+    async fn it_limits_lines_to_a_number_of_chars() {
+        // 9 bytes and 4 characters, and 9 bytes and 5 characters
+        let data = "ééé€\ntöööö\n".as_bytes();
+
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .capacity(3)
+            .max_chars(4, CharOverflow::Truncate)
+            .build(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        assert_stream_eq(rev_lines, vec!["tööö", "ééé€"]).await;
+
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .max_chars(4, CharOverflow::Reject)
+            .build(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        pin_mut!(rev_lines);
+        assert!(matches!(
+            rev_lines.next().await,
+            Some(Err(Error::TooManyChars { offset: 10, max: 4 }))
+        ));
+        assert_eq!(rev_lines.next().await.unwrap().unwrap(), "ééé€");
+        assert!(rev_lines.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn it_keeps_escaped_delimiters_in_lines() {
+        for cap in [1, 2, 3, 4096].iter() {
+            let file = File::open("tests/escaped_newline_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(*cap)
+                .escaped_delimiters(b'\\')
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            assert_stream_eq(
+                rev_lines,
+                vec!["last", "\\\nescaped start", "", "two\\\nthree\\\n", "one"],
+            )
+            .await;
+        }
+
+        let file = File::open("tests/escaped_newline_file").await.unwrap();
+        let rev_lines = RevLines::new(BufReader::new(file)).await.unwrap();
+        assert_stream_eq(
+            rev_lines,
+            vec![
+                "last",
+                "escaped start",
+                "\\",
+                "",
+                "",
+                "three\\",
+                "two\\",
+                "one",
+            ],
+        )
+        .await;
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[tokio::test]
+    async fn it_normalizes_lines() {
+        // Precomposed and decomposed forms of the same words
+        let data = "caf\u{e9}\ncafe\u{301}\nna\u{ef}ve\n";
+
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(3)
+            .normalize(Normalization::Nfc)
+            .build(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        assert_stream_eq(rev_lines, vec!["na\u{ef}ve", "caf\u{e9}", "caf\u{e9}"]).await;
+
+        let rev_lines = RevLinesBuilder::new()
+            .normalize(Normalization::Nfd)
+            .build(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.distinct().map(Result::unwrap).collect().await;
+        assert_eq!(lines, vec!["nai\u{308}ve", "cafe\u{301}"]);
+
+        // Without normalization the two forms are distinct lines
+        let rev_lines = RevLines::new(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        assert_eq!(rev_lines.distinct().count().await, 3);
+    }
+
+    #[tokio::test]
+    async fn it_handles_nuls_by_policy() {
+        let policies = [
+            (NulPolicy::Keep, vec!["\0delta", "gamma", "alpha\0beta"]),
+            (NulPolicy::StripNuls, vec!["delta", "gamma", "alphabeta"]),
+            (
+                NulPolicy::SplitOnNul,
+                vec!["delta", "", "gamma", "beta", "alpha"],
+            ),
+        ];
+
+        for (policy, expected) in policies.iter() {
+            for cap in [1, 4, 4096].iter() {
+                let file = File::open("tests/nul_file").await.unwrap();
+                let rev_lines = RevLinesBuilder::new()
+                    .capacity(*cap)
+                    .nul_policy(*policy)
+                    .build(BufReader::new(file))
+                    .await
+                    .unwrap();
+                assert_stream_eq(rev_lines, expected.clone()).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_skips_empty_lines() {
+        for cap in [1, 4096].iter() {
+            let file = File::open("tests/blank_line_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(*cap)
+                .skip_empty_lines(true)
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            pin_mut!(rev_lines);
+
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), "XYZ");
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), "ABCD");
+            assert!(rev_lines.next().await.is_none());
+            assert_eq!(rev_lines.skipped_lines(), 3);
+        }
+
+        // Whitespace only lines are kept unless blank lines are skipped
+        let data = "a\n \t\n\r\n\nb\n";
+        let rev_lines = RevLinesBuilder::new()
+            .skip_empty_lines(true)
+            .build(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        assert_stream_eq(rev_lines, vec!["b", " \t", "a"]).await;
+
+        let rev_lines = RevLinesBuilder::new()
+            .skip_blank_lines(true)
+            .build(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        assert_stream_eq(rev_lines, vec!["b", "a"]).await;
+    }
+
+    #[tokio::test]
+    async fn it_handles_file_with_multi_lines_and_with_capacity() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_capacity(5, BufReader::new(file))
+            .await
+            .unwrap();
+        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    /// Deterministic xorshift generator for the fuzz-style tests.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    /// Random bytes biased towards new lines, carriage returns and the
+    /// bytes of multi-byte UTF-8 sequences.
+    fn random_bytes(rng: &mut XorShift) -> Vec<u8> {
+        let alphabet = b"\n\n\r\rab\xc3\xa4\xe2\x82\xac\xf0\x9f\xff";
+        let len = rng.below(40) as usize;
+
+        (0..len)
+            .map(|_| alphabet[rng.below(alphabet.len() as u64) as usize])
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_reports_a_reader_that_shrinks() {
+        for absolute_seeks in [false, true].iter() {
+            let reader = MockReader::new(b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n");
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(4)
+                .with_absolute_seeks(*absolute_seeks)
+                .build(BufReader::new(reader))
+                .await
+                .unwrap();
+            pin_mut!(rev_lines);
+
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), "UVWXYZ");
+
+            // Cut the reader down to "ABCDEF\nGHI"
+            rev_lines.reader.get_mut().data.truncate(10);
+
+            // The next chunk starts at 18, past the new end
+            match rev_lines.next().await {
+                Some(Err(Error::Shrunk { offset, len })) => {
+                    assert_eq!(offset, 18);
+                    assert_eq!(len, 30);
+                }
+                other => panic!("expected a shrunk reader, got {:?}", other),
+            }
+            assert!(rev_lines.next().await.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn it_lets_other_tasks_run_every_few_lines() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let data = "line\n".repeat(100);
+
+        for k in [0, 10].iter() {
+            let polls = Arc::new(AtomicUsize::new(0));
+            let other_task = tokio::spawn({
+                let polls = Arc::clone(&polls);
+                async move {
+                    loop {
+                        polls.fetch_add(1, Ordering::SeqCst);
+                        tokio::task::yield_now().await;
+                    }
+                }
+            });
+
+            let reader = BufReader::new(Cursor::new(data.as_bytes()));
+            let rev_lines = RevLines::with_yield_interval(*k, reader).await.unwrap();
+            let lines: Vec<_> = rev_lines.collect().await;
+            let polls = polls.load(Ordering::SeqCst);
+            other_task.abort();
+
+            assert_eq!(lines.len(), 100);
+            if *k == 0 {
+                assert_eq!(polls, 0);
+            } else {
+                assert!(polls >= 9, "{}", polls);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_skips_a_binary_trailer() {
+        for cap in [1, 5, 4096].iter() {
+            let file = File::open("tests/trailer_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(*cap)
+                .trailer_bytes(16)
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            let lines: Vec<_> = rev_lines.map(Result::unwrap).collect().await;
+
+            assert_eq!(lines, vec!["third entry", "second entry", "first entry"]);
+            assert!(lines.iter().all(|line| !line.contains("TRL")));
+        }
+
+        let file = File::open("tests/trailer_file").await.unwrap();
+        let rev_lines = RevLinesBuilder::new()
+            .known_length(53)
+            .trailer_bytes(16)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.map(Result::unwrap).collect().await;
+        assert_eq!(lines, vec!["third entry", "second entry", "first entry"]);
+
+        let reader = BufReader::new(Cursor::new(b"ABC"));
+        let result = RevLines::with_trailer_bytes(4, reader).await;
+        assert!(
+            matches!(result, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reads_small_readers_whole() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let chunked = RevLinesBuilder::new()
+            .capacity(3)
+            .whole_read_threshold(0)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        assert_eq!(chunked.buf_size, 3);
+        let expected: Vec<_> = chunked.map(Result::unwrap).collect().await;
+
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let whole = RevLinesBuilder::new()
+            .capacity(3)
+            .whole_read_threshold(1024)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        assert_eq!(whole.buf_size, whole.len());
+        let lines: Vec<_> = whole.map(Result::unwrap).collect().await;
+
+        assert_eq!(lines, expected);
+        assert_eq!(lines, vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"]);
+    }
+
+    #[cfg(feature = "compress-lines")]
+    #[tokio::test(start_paused = true)]
+    async fn it_compresses_huge_lines_while_assembling_them() {
+        let line = "ACGT".repeat(1 << 18);
+        let data = format!("short\n{}\nend\n", line);
+
+        let mut peaks = Vec::new();
+        for threshold in [None, Some(1 << 16)].iter() {
+            let mut builder = RevLinesBuilder::new();
+            if let Some(threshold) = threshold {
+                builder = builder.compress_lines_over(*threshold);
+            }
+            // Every read waits, so the line can be measured in between
+            let reader = MockReader {
+                read_delay: Some(Duration::from_millis(1)),
+                ..MockReader::new(data.as_bytes())
+            };
+            let rev_lines = builder.build(BufReader::new(reader)).await.unwrap();
+            pin_mut!(rev_lines);
+
+            let mut lines = Vec::new();
+            let mut peak = 0;
+            loop {
+                let polled = futures_util::future::poll_fn(|cx| {
+                    Poll::Ready(rev_lines.as_mut().poll_next(cx))
+                })
+                .await;
+
+                match polled {
+                    Poll::Ready(Some(line)) => lines.push(line.unwrap()),
+                    Poll::Ready(None) => break,
+                    Poll::Pending => {
+                        peak = max(peak, rev_lines.held_line_bytes());
+                        tokio::time::sleep(Duration::from_millis(1)).await;
+                    }
+                }
+            }
+            assert_eq!(lines, vec!["end", line.as_str(), "short"]);
+            peaks.push(peak);
+        }
+
+        assert!(peaks[0] >= line.len(), "{:?}", peaks);
+        assert!(peaks[1] < line.len() / 4, "{:?}", peaks);
+    }
+
+    #[tokio::test]
+    async fn it_uses_a_known_length() {
+        let data = b"ABCDEF\nGHIJK\r\n\nLMNOPQRST\nUVWXYZ";
+
+        for cap in [1, 5, 4096].iter() {
+            let detected = RevLines::with_capacity(*cap, BufReader::new(Cursor::new(&data[..])))
+                .await
+                .unwrap();
+            let expected: Vec<_> = detected.map(Result::unwrap).collect().await;
+
+            let reader = MockReader {
+                end_seeks_fail: true,
+                ..MockReader::new(data)
+            };
+            let known = RevLinesBuilder::new()
+                .capacity(*cap)
+                .known_length(data.len() as u64)
+                .build(BufReader::new(reader))
+                .await
+                .unwrap();
+            let lines: Vec<_> = known.map(Result::unwrap).collect().await;
+
+            assert_eq!(lines, expected);
+        }
+
+        // Only the first `len` bytes are read
+        let reader = BufReader::new(Cursor::new(&data[..]));
+        let rev_lines = RevLines::with_known_length(14, reader).await.unwrap();
+        assert_stream_eq(rev_lines, vec!["GHIJK", "ABCDEF"]).await;
+    }
+
+    #[tokio::test]
+    async fn it_reads_a_borrowed_mutable_buffer() {
+        let mut data = b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n".to_vec();
+
+        let reader = BufReader::new(Cursor::new(&mut data[..]));
+        let rev_lines = RevLines::with_capacity(5, reader).await.unwrap();
+        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+        assert_stream_eq(rev_lines, results).await;
+
+        assert_eq!(data, b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n");
+        data[0] = b'a';
+        assert_eq!(&data[..6], b"aBCDEF");
+    }
+
+    #[tokio::test]
+    async fn it_never_panics_on_random_input() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+
+        for _ in 0..500 {
+            let data = random_bytes(&mut rng);
+            let builder = RevLinesBuilder::new()
+                .capacity(rng.below(8) as usize)
+                .with_absolute_seeks(rng.below(2) == 0)
+                .strict(rng.below(2) == 0)
+                .lossy(rng.below(4) == 0)
+                .to_uppercase(rng.below(4) == 0)
+                .unicode_case(rng.below(2) == 0);
+            let builder = match rng.below(4) {
+                0 => builder.ring(rng.below(data.len() as u64 + 1)),
+                1 => builder.skip_lines_longer_than(rng.below(4) as usize),
+                _ => builder,
+            };
+
+            let rev_lines = builder
+                .build(BufReader::new(MockReader::new(&data)))
+                .await
+                .unwrap();
+            // Errors are fine here, only panics fail the test
+            let lines: Vec<_> = rev_lines.collect().await;
+            assert!(lines.len() <= data.len() + 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_matches_str_lines_on_random_input() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+
+        for _ in 0..500 {
+            let data = random_bytes(&mut rng);
+            let text = String::from_utf8_lossy(&data);
+            let cap = rng.below(8) as usize;
+
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(cap)
+                .lossy(true)
+                .build(BufReader::new(Cursor::new(&data)))
+                .await
+                .unwrap();
+            let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+            let expected: Vec<&str> = text.lines().rev().collect();
+
+            assert_eq!(lines, expected, "{:?} with capacity {}", data, cap);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_never_reads_stale_buffered_bytes() {
+        let mut rng = XorShift(0xd1b5_4a32_d192_ed03);
+
+        for _ in 0..200 {
+            let data: Vec<u8> = (0..rng.below(64))
+                .map(|_| b"ab\n"[rng.below(3) as usize])
+                .collect();
+            let text = String::from_utf8_lossy(&data);
+            let expected: Vec<&str> = text.lines().rev().collect();
+            let cap = rng.below(8) as usize;
+            let buf_reader_cap = 1 + rng.below(16) as usize;
+
+            for absolute_seeks in [false, true].iter() {
+                let mut reader = BufReader::with_capacity(buf_reader_cap, MockReader::new(&data));
+
+                // Leave bytes from the start of the reader in the buffer
+                reader.fill_buf().await.unwrap();
+                reader.consume(min(1, data.len()));
+
+                let rev_lines = RevLinesBuilder::new()
+                    .capacity(cap)
+                    .with_absolute_seeks(*absolute_seeks)
+                    .build(reader)
+                    .await
+                    .unwrap();
+                let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+
+                assert_eq!(
+                    lines, expected,
+                    "{:?} with capacity {} and buffer {}",
+                    text, cap, buf_reader_cap
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_handles_edge_cases_like_str_lines() {
+        let inputs = [
+            "",
+            "\n",
+            "\n\n",
+            "\r\n",
+            "a",
+            "a\n",
+            "a\r\n",
+            "a\r",
+            "\nabc\n",
+            "ab\r\ncd\r\n",
+            "ab\rx",
+            "a\r\r\n",
+            "\r\n\r\n",
+            "x\n\r\n",
+        ];
+
+        for input in inputs.iter() {
+            for cap in 1..6 {
+                let reader = BufReader::new(Cursor::new(input.as_bytes()));
+                let rev_lines = RevLines::with_capacity(cap, reader).await.unwrap();
+                let results = input.lines().rev().collect();
+
+                assert_stream_eq(rev_lines, results).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn it_handles_file_with_multi_lines_and_with_absolute_seeks() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(5)
+            .with_absolute_seeks(true)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_handles_reader_with_only_absolute_seeks() {
+        let data = b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n";
+        let reader = MockReader {
+            start_seeks_only: true,
+            ..MockReader::new(data)
+        };
+
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(5)
+            .with_absolute_seeks(true)
+            .build(BufReader::new(reader))
+            .await
+            .unwrap();
+        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+
+        assert_stream_eq(rev_lines, results).await;
+
+        // Relative seeks go nowhere on this reader
+        let reader = MockReader {
+            start_seeks_only: true,
+            ..MockReader::new(data)
+        };
+        let rev_lines = RevLines::with_capacity(5, BufReader::new(reader))
+            .await
+            .unwrap();
+        pin_mut!(rev_lines);
+
+        assert!(matches!(
+            rev_lines.next().await,
+            Some(Err(Error::Shrunk { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_reads_the_part_after_a_percentage() {
+        // Half of the 30 bytes ends inside "LMNOPQRST"
+        for cap in [1, 4, 4096].iter() {
+            let file = File::open("tests/multi_line_file").await.unwrap();
+            let rev_lines = RevLines::from_percentage(0.5, BufReader::with_capacity(*cap, file))
+                .await
+                .unwrap();
+            assert_eq!(rev_lines.stop_pos, 23);
+            assert_stream_eq(rev_lines, vec!["UVWXYZ"]).await;
+        }
+
+        // An offset right at a line start is kept
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::from_percentage(7.0 / 30.0, BufReader::new(file))
+            .await
+            .unwrap();
+        assert_stream_eq(rev_lines, vec!["UVWXYZ", "LMNOPQRST", "GHIJK"]).await;
+
+        for (pct, lines) in [(0.0, 4), (0.9, 0), (1.0, 0)].iter() {
+            let file = File::open("tests/multi_line_file").await.unwrap();
+            let rev_lines = RevLines::from_percentage(*pct, BufReader::new(file))
+                .await
+                .unwrap();
+            assert_eq!(rev_lines.count().await, *lines);
+        }
+
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let result = RevLines::from_percentage(1.5, BufReader::new(file)).await;
+        assert!(
+            matches!(result, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reads_the_same_chunk_sizes_with_any_buffering() {
+        let data = tokio::fs::read("tests/multi_line_file").await.unwrap();
+
+        for buffer_cap in [1, 5, 8, 8192].iter() {
+            let reader = BufReader::with_capacity(*buffer_cap, MockReader::new(&data));
+            let mut rev_lines = RevLines::with_capacity(8, reader).await.unwrap();
+            let lines = rev_lines.by_ref().count().await;
+
+            assert_eq!(lines, 4);
+            assert_eq!(rev_lines.reader.get_ref().read_lens, vec![8, 8, 8, 6]);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_picks_the_faster_seeks_automatically() {
+        let data = b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n";
+        let slow = Some(Duration::from_millis(5));
+
+        let readers = [
+            (
+                MockReader {
+                    relative_seek_delay: slow,
+                    ..MockReader::new(data)
+                },
+                true,
+            ),
+            (
+                MockReader {
+                    absolute_seek_delay: slow,
+                    ..MockReader::new(data)
+                },
+                false,
+            ),
+        ];
+
+        for (reader, absolute_seeks) in readers {
+            let mut reader = BufReader::new(reader);
+            let strategy = probe_seeks(&mut reader, 30, simulated_now).await.unwrap();
+            assert_eq!(strategy == SeekStrategy::Absolute, absolute_seeks);
+            assert_eq!(reader.stream_position().await.unwrap(), 30);
+        }
+
+        // Whichever kind is picked, the lines are the same
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(5)
+            .seek_strategy(SeekStrategy::Auto)
+            .build(BufReader::new(MockReader::new(data)))
+            .await
+            .unwrap();
+        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_validates_lines_that_are_not_ascii() {
+        let data = "plain\nÄpfel\n"
+            .as_bytes()
+            .iter()
+            .chain(b"ab\x80cd\n\xc3\n")
+            .copied();
+        let reader = BufReader::new(Cursor::new(data.collect::<Vec<_>>()));
+        let mut rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .capacity(3)
+            .build(reader)
+            .await
+            .unwrap();
+        let results: Vec<_> = rev_lines.by_ref().collect().await;
+
+        // Only the plain line skips validation
+        assert_eq!(rev_lines.ascii_lines, 1);
+        assert!(matches!(&results[0], Err(Error::NotUtf8(e)) if e.utf8_error().valid_up_to() == 0));
+        assert!(matches!(&results[1], Err(Error::NotUtf8(e)) if e.utf8_error().valid_up_to() == 2));
+        assert_eq!(results[2].as_ref().unwrap(), "Äpfel");
+        assert_eq!(results[3].as_ref().unwrap(), "plain");
+    }
+
+    #[tokio::test]
+    async fn it_replaces_invalid_utf8_in_lossy_mode() {
+        let file = File::open("tests/invalid_utf8_file").await.unwrap();
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(4)
+            .with_lossy_replacement('?')
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let results = vec!["tail ?", "bad ?? and ? here", "Äpfel"];
+        assert_stream_eq(rev_lines, results).await;
+
+        let file = File::open("tests/invalid_utf8_file").await.unwrap();
+        let rev_lines = RevLinesBuilder::new()
+            .lossy(true)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let results = vec![
+            "tail \u{fffd}",
+            "bad \u{fffd}\u{fffd} and \u{fffd} here",
+            "Äpfel",
+        ];
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_calls_back_once_the_start_is_reached() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let mut rev_lines = RevLines::with_capacity(5, BufReader::new(file))
+            .await
+            .unwrap();
+        let counter = calls.clone();
+        rev_lines.on_start_reached(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        pin_mut!(rev_lines);
+
+        for line in ["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"].iter() {
+            assert!(!rev_lines.reached_start());
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), *line);
+            assert_eq!(calls.load(Ordering::SeqCst), 0);
+        }
+
+        assert!(rev_lines.reached_start());
+        assert!(rev_lines.next().await.is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(rev_lines.next().await.is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The start of a range ends it like the start of the reader
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let mut rev_lines = RevLinesBuilder::new()
+            .stop_at(13)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let counter = calls.clone();
+        rev_lines.on_start_reached(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        pin_mut!(rev_lines);
+
+        assert_eq!(rev_lines.next().await.unwrap().unwrap(), "UVWXYZ");
+        assert_eq!(rev_lines.next().await.unwrap().unwrap(), "LMNOPQRST");
+        assert!(rev_lines.reached_start());
+        assert!(rev_lines.next().await.is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_builds_streams_for_every_profile() {
+        let data = (0..10000)
+            .map(|n| format!("line {}\n", n))
+            .collect::<String>();
+        let expected: Vec<_> = data.lines().rev().collect();
+
+        let mut settings = Vec::new();
+        for profile in [
+            Profile::LowLatency,
+            Profile::Balanced,
+            Profile::HighThroughput,
+        ]
+        .iter()
+        {
+            let reader = BufReader::new(Cursor::new(data.clone()));
+            let rev_lines = RevLines::with_profile(*profile, reader).await.unwrap();
+            settings.push((rev_lines.buf_size, rev_lines.yield_interval));
+
+            assert_stream_eq(rev_lines, expected.clone()).await;
+        }
+
+        // The 97 KiB of lines are read in one go only for high throughput
+        assert_eq!(settings, vec![(1024, 16), (8192, 256), (98890, 0)]);
+    }
+
+    #[tokio::test]
+    async fn it_reports_the_progress_of_a_long_line() {
+        use std::sync::{Arc, Mutex};
+
+        let data = format!("head\n{}\nend\n", "x".repeat(100));
+        let reports = Arc::new(Mutex::new(Vec::new()));
+
+        let reader = BufReader::new(Cursor::new(data.into_bytes()));
+        let mut rev_lines = RevLines::with_capacity(10, reader).await.unwrap();
+        let progress = reports.clone();
+        rev_lines.on_line_progress(move |remaining| progress.lock().unwrap().push(remaining));
+
+        let lines: Vec<_> = rev_lines.by_ref().map(Result::unwrap).collect().await;
+        assert_eq!(
+            lines,
+            vec!["end".to_string(), "x".repeat(100), "head".into()]
+        );
+
+        // Only the long line spans chunks, down to the one with "head\n"
+        let expected: Vec<u64> = (0..=10).rev().map(|chunks| chunks * 10).collect();
+        assert_eq!(*reports.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn it_reports_the_offset_of_invalid_utf8_in_strict_mode() {
+        // The invalid byte is at offset 9 of the reader
+        let data = b"ABCDEF\nGH\xffIJK\nLMN\n";
+
+        for cap in [1, 4, 4096].iter() {
+            let rev_lines = RevLinesBuilder::new()
+                .error_mode(ErrorMode::Yield)
+                .capacity(*cap)
+                .strict(true)
+                .build(BufReader::new(Cursor::new(&data[..])))
+                .await
+                .unwrap();
+            pin_mut!(rev_lines);
+
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), "LMN");
+            match rev_lines.next().await {
+                Some(Err(Error::InvalidUtf8 { offset, source })) => {
+                    assert_eq!(offset, 9);
+                    assert_eq!(source.utf8_error().valid_up_to(), 2);
+                }
+                other => panic!("expected invalid UTF-8, got {:?}", other),
+            }
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), "ABCDEF");
+            assert!(rev_lines.next().await.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn it_handles_a_utf8_sequence_truncated_at_the_end() {
+        // The euro sign is E2 82 AC, the reader is cut off after E2 82
+        let data = b"ABC\nDEF \xe2\x82";
+
+        for cap in [1, 2, 4096].iter() {
+            let rev_lines = RevLinesBuilder::new()
+                .error_mode(ErrorMode::Yield)
+                .capacity(*cap)
+                .build(BufReader::new(Cursor::new(&data[..])))
+                .await
+                .unwrap();
+            let lines: Vec<_> = rev_lines.collect().await;
+            assert!(matches!(&lines[0], Err(Error::NotUtf8(e)) if e.as_bytes() == b"DEF \xe2\x82"));
+            assert_eq!(lines[1].as_ref().unwrap(), "ABC");
+
+            let rev_lines = RevLinesBuilder::new()
+                .error_mode(ErrorMode::Yield)
+                .capacity(*cap)
+                .lossy(true)
+                .build(BufReader::new(Cursor::new(&data[..])))
+                .await
+                .unwrap();
+            let lines: Vec<_> = rev_lines.map(Result::unwrap).collect().await;
+            assert_eq!(lines, vec!["DEF \u{fffd}", "ABC"]);
+
+            let rev_lines = RevLinesBuilder::new()
+                .error_mode(ErrorMode::Yield)
+                .capacity(*cap)
+                .strict(true)
+                .build(BufReader::new(Cursor::new(&data[..])))
+                .await
+                .unwrap();
+            let lines: Vec<_> = rev_lines.collect().await;
+            match &lines[0] {
+                Err(Error::TruncatedUtf8 { offset, .. }) => assert_eq!(*offset, 8),
+                other => panic!("expected a truncated sequence, got {:?}", other),
+            }
+            assert_eq!(lines[1].as_ref().unwrap(), "ABC");
+        }
+
+        // Followed by a line terminator, the sequence is just invalid
+        let data = b"ABC\nDEF \xe2\x82\n";
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .strict(true)
+            .build(BufReader::new(Cursor::new(&data[..])))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.collect().await;
+        assert!(matches!(
+            lines[0],
+            Err(Error::InvalidUtf8 { offset: 8, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_counts_lines_with_a_substring() {
+        for (needle, expected) in [("INFO", 2), ("ERROR", 1), ("O", 3), ("TRACE", 0)].iter() {
+            let file = File::open("tests/severity_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .error_mode(ErrorMode::Yield)
+                .capacity(5)
+                .count_matches(needle)
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            pin_mut!(rev_lines);
+
+            let mut lines = 0;
+            while rev_lines.next().await.is_some() {
+                lines += 1;
+            }
+            assert_eq!(lines, 6);
+            assert_eq!(rev_lines.match_count(), *expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_collects_or_stops_at_errors() {
+        let data = b"ABC\n\xff\nDEF\nGH\xfe\nIJK\n";
+
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(3)
+            .error_mode(ErrorMode::Collect)
+            .build(BufReader::new(Cursor::new(&data[..])))
+            .await
+            .unwrap();
+        pin_mut!(rev_lines);
+
+        let mut lines = Vec::new();
+        while let Some(line) = rev_lines.next().await {
+            lines.push(line.unwrap());
+        }
+        assert_eq!(lines, vec!["IJK", "DEF", "ABC"]);
+
+        let errors = rev_lines.errors();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(&errors[0], Error::NotUtf8(e) if e.as_bytes() == b"GH\xfe"));
+        assert!(matches!(&errors[1], Error::NotUtf8(e) if e.as_bytes() == b"\xff"));
+
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Stop)
+            .build(BufReader::new(Cursor::new(&data[..])))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.collect().await;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].as_ref().unwrap(), "IJK");
+        assert!(matches!(lines[1], Err(Error::NotUtf8(_))));
+
+        let rev_lines = RevLinesBuilder::new()
+            .error_mode(ErrorMode::Yield)
+            .build(BufReader::new(Cursor::new(&data[..])))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.collect().await;
+        assert_eq!(lines.len(), 5);
+
+        // Stopping is the default
+        let rev_lines = RevLines::new(BufReader::new(Cursor::new(&data[..])))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.collect().await;
+        assert_eq!(lines.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn it_reports_inconsistent_seeks_in_strict_mode() {
+        let data = b"ABCDEF\nGHIJK\n";
+
+        let reader = MockReader {
+            seek_report_skew: 1,
+            ..MockReader::new(data)
+        };
+        let rev_lines = RevLinesBuilder::new()
+            .strict(true)
+            .build(BufReader::new(reader))
+            .await
+            .unwrap();
+        pin_mut!(rev_lines);
+
+        let result = rev_lines.next().await;
+        assert!(matches!(
+            result,
+            Some(Err(Error::InconsistentSeek {
+                expected: 0,
+                actual: 1
+            }))
+        ));
+        assert!(rev_lines.next().await.is_none());
+
+        // Without strict mode the reported offsets are not looked at
+        let reader = MockReader {
+            seek_report_skew: 1,
+            ..MockReader::new(data)
+        };
+        let rev_lines = RevLines::new(BufReader::new(reader)).await.unwrap();
+        let results = vec!["GHIJK", "ABCDEF"];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test]
+    async fn it_ends_when_cancelled() {
+        let token = CancellationToken::new();
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let rev_lines = RevLines::with_cancellation(token.clone(), BufReader::new(file))
+            .await
+            .unwrap();
+        pin_mut!(rev_lines);
+
+        assert_eq!(rev_lines.next().await.unwrap().unwrap(), "UVWXYZ");
+        token.cancel();
+        assert!(rev_lines.next().await.is_none());
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[tokio::test]
+    async fn it_ends_when_cancelled_during_a_read() {
+        let token = CancellationToken::new();
+        let reader = MockReader {
+            stall_reads: true,
+            ..MockReader::new(b"ABCD\n")
+        };
+        let rev_lines = RevLines::with_cancellation(token.clone(), BufReader::new(reader))
+            .await
+            .unwrap();
+
+        let lines = tokio::spawn(rev_lines.collect::<Vec<_>>());
+        tokio::task::yield_now().await;
+        token.cancel();
+
+        assert!(lines.await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_falls_back_to_reading_for_the_length() {
+        let data = b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n";
+
+        let reader = MockReader {
+            end_seeks_fail: true,
+            ..MockReader::new(data)
+        };
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(5)
+            .fallback_length(true)
+            .build(BufReader::new(reader))
+            .await
+            .unwrap();
+        let results = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+
+        assert_stream_eq(rev_lines, results).await;
+
+        let reader = MockReader {
+            end_seeks_fail: true,
+            ..MockReader::new(data)
+        };
+        let result = RevLines::new(BufReader::new(reader)).await;
+
+        assert!(
+            matches!(result, Err(Error::Io(e)) if e.kind() == tokio::io::ErrorKind::Unsupported)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_folds_case_of_ascii_letters() {
+        let file = File::open("tests/mixed_case_file").await.unwrap();
+        let rev_lines = RevLinesBuilder::new()
+            .to_lowercase(true)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let results = vec!["mixed", "Äpfel und birnen", "hello world"];
+
+        assert_stream_eq(rev_lines, results).await;
+
+        let file = File::open("tests/mixed_case_file").await.unwrap();
+        let rev_lines = RevLinesBuilder::new()
+            .to_uppercase(true)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let results = vec!["MIXED", "ÄPFEL UND BIRNEN", "HELLO WORLD"];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[tokio::test]
+    async fn it_folds_case_with_unicode_mappings() {
+        let file = File::open("tests/mixed_case_file").await.unwrap();
+        let rev_lines = RevLinesBuilder::new()
+            .to_lowercase(true)
+            .unicode_case(true)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let results = vec!["mixed", "äpfel und birnen", "hello world"];
+
+        assert_stream_eq(rev_lines, results).await;
+
+        // The last case setting wins
+        let file = File::open("tests/mixed_case_file").await.unwrap();
+        let rev_lines = RevLinesBuilder::new()
+            .to_lowercase(true)
+            .to_uppercase(true)
+            .unicode_case(true)
+            .build(BufReader::new(file))
+            .await
+            .unwrap();
+        let results = vec!["MIXED", "ÄPFEL UND BIRNEN", "HELLO WORLD"];
+
+        assert_stream_eq(rev_lines, results).await;
+    }
+
+    #[cfg(feature = "unicode-width")]
+    #[tokio::test]
+    async fn it_pads_lines_to_a_display_width() {
+        use unicode_width::UnicodeWidthStr;
+
+        let data = "ab\n日本\ne\u{301}\nwider than ten\n";
+
+        let rev_lines = RevLinesBuilder::new()
+            .pad_to(10, Pad::Left)
+            .build(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        let results = vec![
+            "wider than ten",
+            "         e\u{301}",
+            "      日本",
+            "        ab",
+        ];
+        assert_stream_eq(rev_lines, results).await;
+
+        let rev_lines = RevLinesBuilder::new()
+            .pad_to(10, Pad::Right)
+            .build(BufReader::new(Cursor::new(data)))
+            .await
+            .unwrap();
+        let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+        let widths: Vec<usize> = lines.iter().map(|line| line.width()).collect();
+        assert_eq!(widths, vec![14, 10, 10, 10]);
+        assert_eq!(lines[2], "日本      ");
+    }
+
+    #[cfg(feature = "time")]
+    #[tokio::test(start_paused = true)]
+    async fn it_ends_at_the_deadline() {
+        let reader = MockReader {
+            read_delay: Some(Duration::from_millis(100)),
+            ..MockReader::new(b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n")
+        };
+        let deadline = Instant::now() + Duration::from_millis(250);
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(8)
+            .deadline(deadline)
+            .build(BufReader::new(reader))
+            .await
+            .unwrap();
+        pin_mut!(rev_lines);
+
+        // Every 8 byte chunk takes 100ms to read, and the second line
+        // needs the third chunk
+        assert_eq!(rev_lines.next().await.unwrap().unwrap(), "UVWXYZ");
+        assert!(matches!(
+            rev_lines.next().await,
+            Some(Err(Error::DeadlineExceeded))
+        ));
+        assert!(rev_lines.next().await.is_none());
+        assert_eq!(Instant::now(), deadline);
+    }
+
+    #[cfg(feature = "time")]
+    #[tokio::test(start_paused = true)]
+    async fn it_retries_transient_errors() {
+        use tokio::io::ErrorKind;
+
+        let data = b"ABCDEF\nGHIJK\n";
+        let policy = RetryPolicy::new(2, Duration::from_millis(10));
+
+        let reader = MockReader {
+            failing_reads: vec![ErrorKind::Interrupted, ErrorKind::TimedOut],
+            ..MockReader::new(data)
+        };
+        let start = Instant::now();
+        let rev_lines = RevLines::with_retry(policy, BufReader::new(reader))
+            .await
+            .unwrap();
+        assert_stream_eq(rev_lines, vec!["GHIJK", "ABCDEF"]).await;
+        assert_eq!(start.elapsed(), Duration::from_millis(30));
+
+        // Out of retries
+        let reader = MockReader {
+            failing_reads: vec![ErrorKind::WouldBlock; 3],
+            ..MockReader::new(data)
+        };
+        let rev_lines = RevLines::with_retry(policy, BufReader::new(reader))
+            .await
+            .unwrap();
+        pin_mut!(rev_lines);
+        let result = rev_lines.next().await;
+        assert!(matches!(result, Some(Err(Error::Io(e))) if e.kind() == ErrorKind::WouldBlock));
+        assert!(rev_lines.next().await.is_none());
+
+        // Errors that aren't transient aren't retried
+        let reader = MockReader {
+            failing_reads: vec![ErrorKind::PermissionDenied],
+            ..MockReader::new(data)
+        };
+        let rev_lines = RevLines::with_retry(policy, BufReader::new(reader))
+            .await
+            .unwrap();
+        pin_mut!(rev_lines);
+        let result = rev_lines.next().await;
+        assert!(
+            matches!(result, Some(Err(Error::Io(e))) if e.kind() == ErrorKind::PermissionDenied)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_handles_ring_buffer_files() {
+        // The newest bytes "rd\nfourth\n" were written over the start of
+        // "first\nsecond\nthird\n", so the next write goes to offset 10
+        for cap in 1..12 {
+            let file = File::open("tests/ring_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(cap)
+                .ring(10)
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            let results = vec!["fourth", "third", "second", "first"];
+
+            assert_stream_eq(rev_lines, results).await;
+        }
+
+        let file = File::open("tests/ring_file").await.unwrap();
+        let rev_lines = RevLines::with_ring(0, BufReader::new(file)).await.unwrap();
+        let results = vec!["thi", "second", "first", "fourth", "rd"];
+
+        assert_stream_eq(rev_lines, results).await;
+
+        let file = File::open("tests/ring_file").await.unwrap();
+        let result = RevLines::with_ring(27, BufReader::new(file)).await;
+
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn it_skips_lines_longer_than_the_limit() {
+        for cap in [3, 7, 100, 4096].iter() {
+            let file = File::open("tests/long_line_file").await.unwrap();
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(*cap)
+                .skip_lines_longer_than(10)
+                .build(BufReader::new(file))
+                .await
+                .unwrap();
+            pin_mut!(rev_lines);
+
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), "end");
+            assert_eq!(rev_lines.skipped_lines(), 0);
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), "ten bytes!");
+            assert_eq!(rev_lines.next().await.unwrap().unwrap(), "short");
+            assert_eq!(rev_lines.skipped_lines(), 1);
+            assert!(rev_lines.next().await.is_none());
+        }
+    }
+
+    thread_local! {
+        static SIMULATED_EPOCH: std::time::Instant = std::time::Instant::now();
+        static SIMULATED_TIME: std::cell::Cell<Duration> = const { std::cell::Cell::new(Duration::ZERO) };
+    }
+
+    /// A clock that only moves when a `MockReader` charges time for a seek.
+    fn simulated_now() -> std::time::Instant {
+        SIMULATED_EPOCH.with(|epoch| *epoch) + SIMULATED_TIME.with(|time| time.get())
+    }
+
+    /// In-memory reader whose seek handling can be broken on purpose.
+    #[derive(Default)]
+    pub(crate) struct MockReader {
+        pub(crate) data: Vec<u8>,
+        pub(crate) pos: u64,
+        // Ignore `SeekFrom::Current` seeks
+        pub(crate) start_seeks_only: bool,
+        // Added to the offset reported after a `SeekFrom::Current` seek
+        pub(crate) seek_report_skew: u64,
+        pub(crate) last_seek_relative: bool,
+        // Never complete a read
+        pub(crate) stall_reads: bool,
+        // Fail `SeekFrom::End` seeks
+        pub(crate) end_seeks_fail: bool,
+        // Fail the next reads with these errors, popped from the back
+        pub(crate) failing_reads: Vec<tokio::io::ErrorKind>,
+        // Advance the simulated clock by this much in every relative or
+        // absolute seek
+        pub(crate) relative_seek_delay: Option<std::time::Duration>,
+        pub(crate) absolute_seek_delay: Option<std::time::Duration>,
+        // The room in the buffer of every read
+        pub(crate) read_lens: Vec<usize>,
+        // Wait this long before completing each read
+        pub(crate) read_delay: Option<std::time::Duration>,
+        pub(crate) delay: Option<Pin<Box<tokio::time::Sleep>>>,
+    }
+
+    impl MockReader {
+        pub(crate) fn new(data: &[u8]) -> Self {
+            MockReader {
+                data: data.to_vec(),
+                ..MockReader::default()
+            }
+        }
+    }
+
+    impl AsyncRead for MockReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<tokio::io::Result<()>> {
+            let this = self.get_mut();
+            if this.stall_reads {
+                return Poll::Pending;
+            }
+
+            this.read_lens.push(buf.remaining());
+
+            if let Some(kind) = this.failing_reads.pop() {
+                return Poll::Ready(Err(kind.into()));
+            }
+
+            if let Some(read_delay) = this.read_delay {
+                let delay = this
+                    .delay
+                    .get_or_insert_with(|| Box::pin(tokio::time::sleep(read_delay)));
+                ready!(delay.as_mut().poll(cx));
+                this.delay = None;
+            }
+
+            let start = min(this.pos as usize, this.data.len());
+            let len = min(buf.remaining(), this.data.len() - start);
+
+            buf.put_slice(&this.data[start..start + len]);
+            this.pos += len as u64;
+
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncSeek for MockReader {
+        fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> tokio::io::Result<()> {
+            let this = self.get_mut();
+            this.last_seek_relative = matches!(pos, SeekFrom::Current(_));
+
+            let seek_delay = match pos {
+                SeekFrom::Current(_) => this.relative_seek_delay,
+                SeekFrom::Start(_) => this.absolute_seek_delay,
+                SeekFrom::End(_) => None,
+            };
+            if let Some(seek_delay) = seek_delay {
+                SIMULATED_TIME.with(|time| time.set(time.get() + seek_delay));
+            }
+
+            if this.end_seeks_fail && matches!(pos, SeekFrom::End(_)) {
+                return Err(tokio::io::ErrorKind::Unsupported.into());
+            }
+
+            match pos {
+                SeekFrom::Start(n) => this.pos = n,
+                SeekFrom::End(n) => this.pos = (this.data.len() as i64 + n) as u64,
+                SeekFrom::Current(_) if this.start_seeks_only => {}
+                SeekFrom::Current(n) => this.pos = (this.pos as i64 + n) as u64,
+            }
+
+            Ok(())
+        }
+
+        fn poll_complete(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+        ) -> Poll<tokio::io::Result<u64>> {
+            if self.last_seek_relative {
+                return Poll::Ready(Ok(self.pos + self.seek_report_skew));
+            }
+
+            Poll::Ready(Ok(self.pos))
+        }
+    }
+
+    async fn assert_stream_eq(
+        rev_lines: impl Stream<Item = Result<String, Error>>,
+        results: Vec<&str>,
+    ) {
+        pin_mut!(rev_lines);
+
+        for result in results {
+            let equals = if let Some(Ok(line)) = rev_lines.next().await {
+                line == result
+            } else {
+                false
+            };
+            assert!(equals)
+        }
+
+        assert!(rev_lines.next().await.is_none());
+    }
+}
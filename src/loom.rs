@@ -0,0 +1,97 @@
+//! Models of the poll loop under `loom`, checking that no interleaving of a
+//! reader waking the task with the task polling the stream loses a wakeup or
+//! bytes. Run with `RUSTFLAGS="--cfg loom" cargo test --lib loom`.
+
+use crate::RevLines;
+use futures_util::StreamExt;
+use loom::future::AtomicWaker;
+use loom::sync::atomic::{AtomicBool, Ordering};
+use loom::sync::Arc;
+use loom::thread;
+use std::io::{Cursor, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, BufReader, ReadBuf};
+
+/// Pending until another thread opens its gate, then reads and seeks `data`.
+struct GatedReader {
+    data: Cursor<Vec<u8>>,
+    gate: Arc<Gate>,
+}
+
+struct Gate {
+    open: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Gate {
+    fn open(&self) {
+        self.open.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+
+    fn poll_open(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.open.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+
+        self.waker.register_by_ref(cx.waker());
+
+        // The gate may have been opened before the waker was registered
+        if self.open.load(Ordering::Acquire) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl AsyncRead for GatedReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<tokio::io::Result<()>> {
+        let this = self.get_mut();
+        std::task::ready!(this.gate.poll_open(cx));
+        Pin::new(&mut this.data).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for GatedReader {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> tokio::io::Result<()> {
+        Pin::new(&mut self.get_mut().data).start_seek(pos)
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<tokio::io::Result<u64>> {
+        let this = self.get_mut();
+        std::task::ready!(this.gate.poll_open(cx));
+        Pin::new(&mut this.data).poll_complete(cx)
+    }
+}
+
+#[test]
+fn it_wakes_up_once_the_reader_is_ready() {
+    loom::model(|| {
+        let gate = Arc::new(Gate {
+            open: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        });
+        let reader = GatedReader {
+            data: Cursor::new(b"ab\ncd\n".to_vec()),
+            gate: Arc::clone(&gate),
+        };
+
+        let opener = thread::spawn(move || gate.open());
+
+        let lines: Vec<_> = loom::future::block_on(async {
+            let rev_lines = RevLines::with_capacity(2, BufReader::new(reader))
+                .await
+                .unwrap();
+            rev_lines.map(|line| line.unwrap()).collect().await
+        });
+
+        opener.join().unwrap();
+        assert_eq!(lines, vec!["cd", "ab"]);
+    });
+}
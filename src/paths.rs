@@ -0,0 +1,204 @@
+//! Reading the lines of several files in one stream, last file first.
+
+use crate::{Error, RevLines, RevLinesBuilder};
+use futures_util::Stream;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::fs::File;
+use tokio::io::BufReader;
+
+type OpenFuture = Pin<Box<dyn Future<Output = Result<RevLines<File>, Error>> + Send>>;
+
+/// An async stream of the lines of several files, last line of the last file
+/// first. Created by [`RevLines::from_paths`].
+///
+/// Each file is only opened once the files after it are exhausted, so at most
+/// one of them is open at a time. Files are read separately: the first line of
+/// a file is never joined with the last line of the file before it.
+pub struct RevPaths {
+    builder: RevLinesBuilder,
+    paths: Vec<PathBuf>,
+    opening: Option<OpenFuture>,
+    current: Option<RevLines<File>>,
+}
+
+impl RevLines<File> {
+    /// Create an async stream of strings from the files at `paths`, read
+    /// backwards starting with the last path. If a file can't be opened,
+    /// [`Error::Open`] is yielded and the path before it is read next.
+    pub fn from_paths(paths: Vec<PathBuf>) -> RevPaths {
+        RevLinesBuilder::new().build_from_paths(paths)
+    }
+}
+
+impl RevLinesBuilder {
+    /// Bound the memory buffering the files of
+    /// [`RevLinesBuilder::build_from_paths`] to `bytes`. Only one file is open
+    /// at a time, so the budget covers that file's chunk buffer and the
+    /// buffer of its `BufReader`, which get half of it each. The capacity is
+    /// lowered to fit if needed. The line being assembled isn't counted,
+    /// since it grows with the longest line whatever the budget.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Create an async stream of strings from the files at `paths`. See
+    /// [`RevLines::from_paths`].
+    pub fn build_from_paths(self, paths: Vec<PathBuf>) -> RevPaths {
+        RevPaths {
+            builder: self,
+            paths,
+            opening: None,
+            current: None,
+        }
+    }
+}
+
+async fn open(builder: RevLinesBuilder, path: PathBuf) -> Result<RevLines<File>, Error> {
+    let file = match File::open(&path).await {
+        Ok(file) => file,
+        Err(source) => return Err(Error::Open { path, source }),
+    };
+
+    match builder.memory_budget {
+        Some(budget) => {
+            let capacity = builder.capacity.min(budget / 2);
+            let reader = BufReader::with_capacity(budget - capacity, file);
+            builder.capacity(capacity).build(reader).await
+        }
+        None => builder.build(BufReader::new(file)).await,
+    }
+}
+
+impl Stream for RevPaths {
+    type Item = Result<String, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(opening) = &mut this.opening {
+                let result = ready!(opening.as_mut().poll(cx));
+                this.opening = None;
+
+                match result {
+                    Ok(rev_lines) => this.current = Some(rev_lines),
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                }
+            }
+
+            if let Some(current) = &mut this.current {
+                match ready!(Pin::new(current).poll_next(cx)) {
+                    Some(line) => return Poll::Ready(Some(line)),
+                    None => this.current = None,
+                }
+            }
+
+            match this.paths.pop() {
+                Some(path) => this.opening = Some(Box::pin(open(this.builder.clone(), path))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use futures_util::{pin_mut, StreamExt};
+    use std::path::Path;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tokio-rev-lines-{}-{}", std::process::id(), name))
+    }
+
+    #[tokio::test]
+    async fn it_reads_paths_backwards_and_lazily() {
+        let paths = vec![
+            temp_path("paths-1"),
+            temp_path("paths-2"),
+            temp_path("paths-3"),
+        ];
+        std::fs::write(&paths[1], "C\nD\n").unwrap();
+        std::fs::write(&paths[2], "E\nF\n").unwrap();
+
+        let rev_paths = RevLines::from_paths(paths.clone());
+        pin_mut!(rev_paths);
+
+        assert_eq!(rev_paths.next().await.unwrap().unwrap(), "F");
+        assert_eq!(rev_paths.next().await.unwrap().unwrap(), "E");
+        assert_eq!(rev_paths.next().await.unwrap().unwrap(), "D");
+
+        // The first file is only opened after the second one is exhausted
+        std::fs::write(&paths[0], "A\nB").unwrap();
+
+        let rest: Vec<_> = rev_paths.map(|line| line.unwrap()).collect().await;
+        assert_eq!(rest, vec!["C", "B", "A"]);
+
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn it_keeps_buffers_within_the_memory_budget() {
+        let paths: Vec<_> = (0..4)
+            .map(|i| temp_path(&format!("budget-{}", i)))
+            .collect();
+        for (i, path) in paths.iter().enumerate() {
+            std::fs::write(path, format!("{}\n", i).repeat(1000)).unwrap();
+        }
+
+        let rev_paths = RevLinesBuilder::new()
+            .memory_budget(100)
+            .build_from_paths(paths.clone());
+        pin_mut!(rev_paths);
+
+        let mut lines = 0;
+        let mut peak = 0;
+        while let Some(line) = rev_paths.next().await {
+            line.unwrap();
+            lines += 1;
+
+            // `BufReader` doesn't tell its capacity, only what it holds
+            let current = rev_paths.current.as_ref().unwrap();
+            assert!(current.buf_size <= 50);
+            let held = current.buf.capacity() + current.reader.buffer().len();
+            peak = peak.max(held);
+        }
+
+        assert_eq!(lines, 4000);
+        assert!(peak <= 100, "{}", peak);
+
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reports_files_that_cannot_be_opened() {
+        let paths = vec![
+            temp_path("open-1"),
+            temp_path("open-missing"),
+            temp_path("open-3"),
+        ];
+        std::fs::write(&paths[0], "A\n").unwrap();
+        std::fs::write(&paths[2], "C\n").unwrap();
+
+        let results: Vec<_> = RevLines::from_paths(paths.clone()).collect().await;
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), "C");
+        assert!(
+            matches!(&results[1], Err(Error::Open { path, .. }) if path == Path::new(&paths[1]))
+        );
+        assert_eq!(results[2].as_ref().unwrap(), "A");
+
+        std::fs::remove_file(&paths[0]).unwrap();
+        std::fs::remove_file(&paths[2]).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+//! Reading lines ahead of the consumer in a background task.
+
+use crate::{Error, Profile, RevLines};
+use futures_util::{Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, BufReader};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// A line or error sent to the subscribers of [`RevLines::broadcast`].
+pub type BroadcastLine = Result<Arc<String>, Arc<Error>>;
+
+/// An async stream of the lines of a [`RevLines`] stream that is read ahead
+/// by a background task. Created by [`RevLines::with_prefetch`] and
+/// [`RevLines::prefetch`].
+///
+/// The task stops reading ahead once `depth` lines are waiting to be
+/// consumed, and is aborted when the stream is dropped.
+pub struct Prefetch {
+    lines: mpsc::Receiver<Result<String, Error>>,
+    task: JoinHandle<()>,
+}
+
+impl<R: AsyncSeek + AsyncRead + Unpin + Send + 'static> RevLines<R> {
+    /// Create an async stream of strings from a `BufReader<R>` that reads up
+    /// to `depth` lines ahead while the consumer processes the current one.
+    /// Pipelining the reads this way helps with high-latency readers. Must be
+    /// called from within a tokio runtime.
+    pub async fn with_prefetch(depth: usize, reader: BufReader<R>) -> Result<Prefetch, Error> {
+        Ok(RevLines::new(reader).await?.prefetch(depth))
+    }
+
+    /// Create an async stream of strings from a `BufReader<R>` tuned for
+    /// `profile`, reading as many lines ahead as the profile prefetches. See
+    /// [`RevLines::with_profile`] and [`RevLines::with_prefetch`].
+    pub async fn prefetch_with_profile(
+        profile: Profile,
+        reader: BufReader<R>,
+    ) -> Result<Prefetch, Error> {
+        let rev_lines = RevLines::with_profile(profile, reader).await?;
+        Ok(rev_lines.prefetch(profile.prefetch_depth()))
+    }
+
+    /// Read up to `depth` lines ahead in a background task, see
+    /// [`RevLines::with_prefetch`]. A `depth` of 0 is treated as 1.
+    pub fn prefetch(mut self, depth: usize) -> Prefetch {
+        let (sender, lines) = mpsc::channel(depth.max(1));
+
+        let task = tokio::spawn(async move {
+            while let Some(line) = self.next().await {
+                if sender.send(line).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Prefetch { lines, task }
+    }
+
+    /// Read the lines in a background task and send each of them to every
+    /// subscriber of a broadcast channel holding up to `capacity` lines. More
+    /// subscribers are added with `Receiver::resubscribe`, and only receive
+    /// the lines sent after they subscribed.
+    ///
+    /// The task doesn't wait for slow subscribers: one that falls more than
+    /// `capacity` lines behind gets `RecvError::Lagged` and misses the oldest
+    /// lines. Errors are sent like lines, so subscribers can tell a stream
+    /// that ended on an error from one that read every line. The channel is
+    /// closed once the stream ends or when every subscriber is dropped. Must
+    /// be called from within a tokio runtime, and panics if `capacity` is 0.
+    pub fn broadcast(mut self, capacity: usize) -> broadcast::Receiver<BroadcastLine> {
+        let (sender, receiver) = broadcast::channel(capacity);
+
+        tokio::spawn(async move {
+            while let Some(line) = self.next().await {
+                if sender.send(line.map(Arc::new).map_err(Arc::new)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        receiver
+    }
+}
+
+impl Stream for Prefetch {
+    type Item = Result<String, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().lines.poll_recv(cx)
+    }
+}
+
+impl Drop for Prefetch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use crate::tests::MockReader;
+    use std::io::Cursor;
+    use std::time::Duration;
+    use tokio::time::{sleep, Instant};
+
+    fn delayed_reader() -> BufReader<MockReader> {
+        let reader = MockReader {
+            read_delay: Some(Duration::from_millis(100)),
+            ..MockReader::new(b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n")
+        };
+        BufReader::new(reader)
+    }
+
+    // Process each line for 100ms, returning the lines and the time taken
+    async fn consume<S>(lines: S) -> (Vec<String>, Duration)
+    where
+        S: Stream<Item = Result<String, Error>>,
+    {
+        let start = Instant::now();
+        let lines = lines
+            .then(|line| async move {
+                sleep(Duration::from_millis(100)).await;
+                line.unwrap()
+            })
+            .collect()
+            .await;
+
+        (lines, start.elapsed())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_reads_ahead_while_lines_are_processed() {
+        let expected = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+
+        let rev_lines = RevLines::with_capacity(8, delayed_reader()).await.unwrap();
+        let (lines, sequential) = consume(rev_lines).await;
+        assert_eq!(lines, expected);
+
+        let rev_lines = RevLines::with_capacity(8, delayed_reader()).await.unwrap();
+        let (lines, prefetched) = consume(rev_lines.prefetch(2)).await;
+        assert_eq!(lines, expected);
+
+        // 4 chunks take 400ms to read and 4 lines 400ms to process
+        assert_eq!(sequential, Duration::from_millis(800));
+        assert!(prefetched < sequential, "{:?}", prefetched);
+    }
+
+    #[tokio::test]
+    async fn it_prefetches_as_many_lines_as_the_profile() {
+        let expected = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+
+        let mut depths = Vec::new();
+        for profile in [
+            Profile::LowLatency,
+            Profile::Balanced,
+            Profile::HighThroughput,
+        ]
+        .iter()
+        {
+            let reader = BufReader::new(Cursor::new(b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n"));
+            let prefetch = RevLines::prefetch_with_profile(*profile, reader)
+                .await
+                .unwrap();
+            depths.push(prefetch.lines.max_capacity());
+
+            let lines: Vec<_> = prefetch.map(Result::unwrap).collect().await;
+            assert_eq!(lines, expected);
+        }
+
+        assert_eq!(depths, vec![1, 64, 1024]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_broadcasts_lines_to_every_subscriber() {
+        async fn receive(mut receiver: broadcast::Receiver<BroadcastLine>) -> Vec<String> {
+            let mut lines = Vec::new();
+            while let Ok(line) = receiver.recv().await {
+                lines.push(line.unwrap().to_string());
+            }
+            lines
+        }
+
+        let rev_lines = RevLines::with_capacity(5, delayed_reader()).await.unwrap();
+        let first = rev_lines.broadcast(16);
+        let second = first.resubscribe();
+
+        let (first, second) = tokio::join!(receive(first), receive(second));
+        let expected = vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"];
+        assert_eq!(first, expected);
+        assert_eq!(second, expected);
+    }
+
+    #[tokio::test]
+    async fn it_broadcasts_the_error_that_ended_the_stream() {
+        let reader = BufReader::new(Cursor::new(b"ok\n\xff\nabc\n".to_vec()));
+        let rev_lines = RevLines::new(reader).await.unwrap();
+        let mut receiver = rev_lines.broadcast(16);
+
+        assert_eq!(*receiver.recv().await.unwrap().unwrap(), "abc");
+        let error = receiver.recv().await.unwrap().unwrap_err();
+        assert!(matches!(*error, Error::NotUtf8(_)));
+        assert!(matches!(
+            receiver.recv().await,
+            Err(broadcast::error::RecvError::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_passes_errors_through() {
+        let reader = BufReader::new(Cursor::new(b"\xff\nabc\n".to_vec()));
+        let prefetch = RevLines::with_prefetch(0, reader).await.unwrap();
+        let lines: Vec<_> = prefetch.collect().await;
+
+        assert_eq!(lines[0].as_ref().unwrap(), "abc");
+        assert!(matches!(lines[1], Err(Error::NotUtf8(_))));
+        assert_eq!(lines.len(), 2);
+    }
+}
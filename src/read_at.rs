@@ -0,0 +1,339 @@
+//! Reading with several chunk reads in flight at once, for readers that
+//! support positioned reads.
+
+use crate::{Error, RevLines, RevLinesBuilder};
+use futures_util::{stream, Stream, StreamExt};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, BufReader, ReadBuf, SeekFrom};
+
+/// Future returned by the methods of [`ReadAt`].
+pub type ReadAtFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+type ChunkStream = Pin<Box<dyn Stream<Item = io::Result<(u64, Vec<u8>)>> + Send>>;
+
+/// A reader that can read at any offset without moving a shared cursor, so
+/// several reads can be in flight at once.
+pub trait ReadAt {
+    /// Read exactly `len` bytes starting at `offset`.
+    fn read_at(&self, offset: u64, len: usize) -> ReadAtFuture<'_, Vec<u8>>;
+
+    /// The number of bytes in the reader.
+    fn size(&self) -> ReadAtFuture<'_, u64>;
+}
+
+/// Adapts a [`ReadAt`] reader for [`RevLines`] by reading the chunks the
+/// stream is going to ask for ahead of time. Created by
+/// [`RevLines::with_concurrent_reads`].
+pub struct ConcurrentReader<P> {
+    reader: Arc<P>,
+    len: u64,
+    pos: u64,
+    // Chunks in the order the stream reads them, last chunk first
+    chunks: ChunkStream,
+    current: Option<(u64, Vec<u8>)>,
+}
+
+impl<P: ReadAt + Send + Sync + 'static> RevLines<ConcurrentReader<P>> {
+    /// Create an async stream of strings from a positioned reader, keeping up
+    /// to `n` chunk reads in flight while the stream is polled. The chunks
+    /// are reassembled in order, so lines come out as with any other reader.
+    /// Helps with high-latency readers like object stores.
+    ///
+    /// Reads only make progress while the stream is polled. To keep them
+    /// going while lines are processed, also use `RevLines::prefetch` from
+    /// the `rt` feature.
+    pub async fn with_concurrent_reads(n: usize, reader: Arc<P>) -> Result<Self, Error> {
+        RevLinesBuilder::new().build_concurrent(n, reader).await
+    }
+}
+
+impl RevLinesBuilder {
+    /// Create the async stream of strings from a positioned reader. See
+    /// [`RevLines::with_concurrent_reads`]. Ring buffer readers aren't
+    /// supported, [`RevLinesBuilder::ring`] is ignored.
+    pub async fn build_concurrent<P: ReadAt + Send + Sync + 'static>(
+        mut self,
+        n: usize,
+        reader: Arc<P>,
+    ) -> Result<RevLines<ConcurrentReader<P>>, Error> {
+        // The reader ends for the stream where the trailer starts
+        let len = self.without_trailer(reader.size().await?)?;
+        let buf_size = self.chunk_size(len);
+
+        // The same chunks `RevLines` reads, from the end of the reader
+        let ends = (1..=len).rev().step_by(buf_size as usize);
+        let chunk_reader = reader.clone();
+        let chunks = stream::iter(ends)
+            .map(move |end| {
+                let reader = chunk_reader.clone();
+                let start = end.saturating_sub(buf_size);
+                async move { read_chunk(&*reader, start, (end - start) as usize).await }
+            })
+            .buffered(n.max(1));
+
+        let concurrent_reader = ConcurrentReader {
+            reader,
+            len,
+            pos: 0,
+            chunks: Box::pin(chunks),
+            current: None,
+        };
+
+        self.ring_start = None;
+        self.with_absolute_seeks(true)
+            .build_with_size(BufReader::new(concurrent_reader), len)
+    }
+}
+
+async fn read_chunk<P: ReadAt>(reader: &P, offset: u64, len: usize) -> io::Result<(u64, Vec<u8>)> {
+    let chunk = reader.read_at(offset, len).await?;
+    if chunk.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok((offset, chunk))
+}
+
+impl<P> ConcurrentReader<P> {
+    /// The positioned reader being read from.
+    pub fn get_ref(&self) -> &P {
+        &self.reader
+    }
+}
+
+impl<P> AsyncRead for ConcurrentReader<P> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some((offset, chunk)) = &this.current {
+                let end = offset + chunk.len() as u64;
+                if *offset <= this.pos && this.pos < end {
+                    let start = (this.pos - offset) as usize;
+                    let len = buf.remaining().min(chunk.len() - start);
+                    buf.put_slice(&chunk[start..start + len]);
+                    this.pos += len as u64;
+                    return Poll::Ready(Ok(()));
+                }
+            }
+
+            if this.pos >= this.len {
+                return Poll::Ready(Ok(()));
+            }
+
+            match ready!(this.chunks.as_mut().poll_next(cx)) {
+                Some(chunk) => this.current = Some(chunk?),
+                // Out of chunks, so `RevLines` fails with `Error::Shrunk`
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+impl<P> AsyncSeek for ConcurrentReader<P> {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => this.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => this.pos.checked_add_signed(delta),
+        };
+
+        match pos {
+            Some(pos) => {
+                this.pos = pos;
+                Ok(())
+            }
+            None => Err(io::ErrorKind::InvalidInput.into()),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use crate::Profile;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// In-memory positioned reader that takes 100ms per read and records
+    /// how many reads overlapped.
+    struct SlowReader {
+        data: Vec<u8>,
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl SlowReader {
+        fn new(data: &[u8]) -> Arc<Self> {
+            Arc::new(SlowReader {
+                data: data.to_vec(),
+                in_flight: AtomicUsize::new(0),
+                max_in_flight: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    impl ReadAt for SlowReader {
+        fn read_at(&self, offset: u64, len: usize) -> ReadAtFuture<'_, Vec<u8>> {
+            Box::pin(async move {
+                let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                let start = offset as usize;
+                Ok(self.data[start..start + len].to_vec())
+            })
+        }
+
+        fn size(&self) -> ReadAtFuture<'_, u64> {
+            Box::pin(async move { Ok(self.data.len() as u64) })
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_reassembles_concurrent_reads_in_order() {
+        let data = b"ABCDEF\nGHIJK\r\nLMNOPQRST\n\nUVWXYZ";
+
+        for n in 1..5 {
+            for cap in [1, 3, 5, 64].iter() {
+                let reader = SlowReader::new(data);
+                let rev_lines = RevLinesBuilder::new()
+                    .capacity(*cap)
+                    .build_concurrent(n, reader.clone())
+                    .await
+                    .unwrap();
+
+                let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+                assert_eq!(lines, vec!["UVWXYZ", "", "LMNOPQRST", "GHIJK", "ABCDEF"]);
+
+                let chunks = data.len().div_ceil(*cap);
+                assert_eq!(reader.max_in_flight.load(Ordering::SeqCst), n.min(chunks));
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_overlaps_up_to_n_reads() {
+        let data = b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n";
+        let reader = SlowReader::new(data);
+        let start = tokio::time::Instant::now();
+
+        // 30 bytes in 10 chunks of 3, 4 chunks at a time
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(3)
+            .build_concurrent(4, reader.clone())
+            .await
+            .unwrap();
+        let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+
+        assert_eq!(lines, vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"]);
+        assert_eq!(reader.max_in_flight.load(Ordering::SeqCst), 4);
+        assert!(start.elapsed() < Duration::from_millis(1000));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_reads_small_readers_in_one_go() {
+        let data = (0..200).map(|n| format!("line{}\n", n)).collect::<String>();
+
+        for threshold in [0, 1 << 20].iter() {
+            let reader = SlowReader::new(data.as_bytes());
+            let rev_lines = RevLinesBuilder::new()
+                .capacity(16)
+                .whole_read_threshold(*threshold)
+                .build_concurrent(4, reader.clone())
+                .await
+                .unwrap();
+
+            let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+            assert_eq!(lines.len(), 200);
+            assert_eq!(lines[0], "line199");
+            assert_eq!(lines[199], "line0");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_leaves_out_the_trailer() {
+        let data = (0..200).map(|n| format!("line{}\n", n)).collect::<String>();
+        let reader = SlowReader::new(data.as_bytes());
+        let rev_lines = RevLinesBuilder::new()
+            .capacity(16)
+            .trailer_bytes(9)
+            .build_concurrent(4, reader)
+            .await
+            .unwrap();
+
+        let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+        assert_eq!(lines.len(), 199);
+        assert_eq!(lines[0], "line198");
+        assert_eq!(lines[198], "line0");
+
+        let reader = SlowReader::new(b"short");
+        let result = RevLinesBuilder::new()
+            .trailer_bytes(9)
+            .build_concurrent(4, reader)
+            .await;
+        assert!(matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::InvalidInput));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_reads_with_every_profile() {
+        let data = (0..200).map(|n| format!("line{}\n", n)).collect::<String>();
+
+        for profile in [
+            Profile::LowLatency,
+            Profile::Balanced,
+            Profile::HighThroughput,
+        ]
+        .iter()
+        {
+            let reader = SlowReader::new(data.as_bytes());
+            let rev_lines = RevLinesBuilder::new()
+                .profile(*profile)
+                .build_concurrent(4, reader)
+                .await
+                .unwrap();
+
+            let lines: Vec<String> = rev_lines.map(Result::unwrap).collect().await;
+            assert_eq!(lines.len(), 200);
+            assert_eq!(lines[0], "line199");
+        }
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_short_reads() {
+        struct ShortReader;
+
+        impl ReadAt for ShortReader {
+            fn read_at(&self, _offset: u64, _len: usize) -> ReadAtFuture<'_, Vec<u8>> {
+                Box::pin(async { Ok(b"a".to_vec()) })
+            }
+
+            fn size(&self) -> ReadAtFuture<'_, u64> {
+                Box::pin(async { Ok(10) })
+            }
+        }
+
+        let rev_lines = RevLines::with_concurrent_reads(2, Arc::new(ShortReader))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.collect().await;
+
+        assert_eq!(lines.len(), 1);
+        assert!(matches!(&lines[0], Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof));
+    }
+}
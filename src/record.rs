@@ -0,0 +1,248 @@
+//! Recording the I/O of a stream, and replaying it without the reader, so a
+//! bug seen with a particular file can be reproduced from its log alone.
+
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf, SeekFrom};
+
+/// One completed operation on a reader, see [`RecordingReader`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IoOp {
+    /// A seek to `pos` that ended at `offset`.
+    Seek { pos: SeekFrom, offset: u64 },
+    /// A read with room for `len` bytes that returned `bytes`.
+    Read { len: usize, bytes: Vec<u8> },
+}
+
+/// The operations a [`RecordingReader`] has seen, shared with the reader so
+/// it can be looked at once the stream owning the reader is gone.
+#[derive(Clone, Debug, Default)]
+pub struct IoLog {
+    ops: Arc<Mutex<Vec<IoOp>>>,
+}
+
+impl IoLog {
+    /// The operations recorded so far, in the order they completed.
+    pub fn ops(&self) -> Vec<IoOp> {
+        self.ops
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn push(&self, op: IoOp) {
+        self.ops
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(op);
+    }
+}
+
+/// A reader that passes everything through to `R` and records every seek
+/// and read that succeeds in an [`IoLog`], for a [`ReplayReader`] to replay.
+/// Failed operations aren't recorded.
+pub struct RecordingReader<R> {
+    inner: R,
+    log: IoLog,
+    seek: Option<SeekFrom>,
+}
+
+impl<R> RecordingReader<R> {
+    /// Record the operations on `inner`.
+    pub fn new(inner: R) -> Self {
+        RecordingReader {
+            inner,
+            log: IoLog::default(),
+            seek: None,
+        }
+    }
+
+    /// The log the operations are recorded in.
+    pub fn log(&self) -> IoLog {
+        self.log.clone()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for RecordingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let len = buf.remaining();
+        let filled = buf.filled().len();
+
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.log.push(IoOp::Read {
+            len,
+            bytes: buf.filled()[filled..].to_vec(),
+        });
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncSeek + Unpin> AsyncSeek for RecordingReader<R> {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        Pin::new(&mut this.inner).start_seek(pos)?;
+        this.seek = Some(pos);
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        let result = ready!(Pin::new(&mut this.inner).poll_complete(cx));
+
+        // Only completions of a seek that was started are operations
+        if let Some(pos) = this.seek.take() {
+            if let Ok(offset) = result {
+                this.log.push(IoOp::Seek { pos, offset });
+            }
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+/// A reader that replays the operations of an [`IoLog`] instead of reading
+/// anything. Every seek and read has to match the next operation of the
+/// log, with the same seek position or the same room for bytes, or it fails
+/// with `ErrorKind::InvalidData`. A stream built with the same settings as
+/// the recorded one therefore yields the same lines.
+pub struct ReplayReader {
+    ops: VecDeque<IoOp>,
+    seek: Option<SeekFrom>,
+    pos: u64,
+}
+
+impl ReplayReader {
+    /// Replay `ops`, as returned by [`IoLog::ops`].
+    pub fn new(ops: Vec<IoOp>) -> Self {
+        ReplayReader {
+            ops: ops.into(),
+            seek: None,
+            pos: 0,
+        }
+    }
+
+    fn mismatch(op: Option<IoOp>, expected: &str) -> io::Error {
+        let msg = match op {
+            Some(op) => format!("expected {}, but the log has {:?}", expected, op),
+            None => format!("expected {}, but the log has ended", expected),
+        };
+
+        io::Error::new(io::ErrorKind::InvalidData, msg)
+    }
+}
+
+impl AsyncRead for ReplayReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        let bytes = match this.ops.pop_front() {
+            Some(IoOp::Read { len, bytes }) if len == buf.remaining() => bytes,
+            op => {
+                let expected = format!("a read of {} bytes", buf.remaining());
+                return Poll::Ready(Err(Self::mismatch(op, &expected)));
+            }
+        };
+
+        buf.put_slice(&bytes);
+        this.pos += bytes.len() as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for ReplayReader {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        self.get_mut().seek = Some(pos);
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+
+        let pos = match this.seek.take() {
+            Some(pos) => pos,
+            None => return Poll::Ready(Ok(this.pos)),
+        };
+
+        match this.ops.pop_front() {
+            Some(IoOp::Seek {
+                pos: logged,
+                offset,
+            }) if logged == pos => {
+                this.pos = offset;
+                Poll::Ready(Ok(offset))
+            }
+            op => {
+                let expected = format!("a seek to {:?}", pos);
+                Poll::Ready(Err(Self::mismatch(op, &expected)))
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use crate::{Error, RevLines};
+    use futures_util::StreamExt;
+    use tokio::fs::File;
+    use tokio::io::BufReader;
+
+    #[tokio::test]
+    async fn it_replays_recorded_io() {
+        let file = File::open("tests/multi_line_file").await.unwrap();
+        let reader = RecordingReader::new(file);
+        let log = reader.log();
+
+        let rev_lines = RevLines::with_capacity(8, BufReader::new(reader))
+            .await
+            .unwrap();
+        let lines: Vec<_> = rev_lines.map(Result::unwrap).collect().await;
+        assert_eq!(lines, vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"]);
+
+        let ops = log.ops();
+        assert_eq!(
+            ops[0],
+            IoOp::Seek {
+                pos: SeekFrom::End(0),
+                offset: 30
+            }
+        );
+        let read_lens: Vec<_> = ops
+            .iter()
+            .filter_map(|op| match op {
+                IoOp::Read { len, .. } => Some(*len),
+                IoOp::Seek { .. } => None,
+            })
+            .collect();
+        assert_eq!(read_lens, vec![8, 8, 8, 6]);
+
+        // The same settings replay the same lines without the file
+        let reader = BufReader::new(ReplayReader::new(ops.clone()));
+        let rev_lines = RevLines::with_capacity(8, reader).await.unwrap();
+        let replayed: Vec<_> = rev_lines.map(Result::unwrap).collect().await;
+        assert_eq!(replayed, lines);
+
+        // Other settings do other I/O, which the log can't answer
+        let reader = BufReader::new(ReplayReader::new(ops));
+        let rev_lines = RevLines::with_capacity(5, reader).await.unwrap();
+        let replayed: Vec<_> = rev_lines.collect().await;
+        assert!(matches!(
+            &replayed[..],
+            [Err(Error::Io(e))] if e.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+}
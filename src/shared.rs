@@ -0,0 +1,319 @@
+//! Sharing one reader between several streams, each with its own position.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf, SeekFrom};
+
+/// A handle to a reader shared behind an `Arc<Mutex<_>>`, for reading
+/// different parts of one file from several [`RevLines`](crate::RevLines)
+/// streams at once.
+///
+/// Every handle keeps its own position. A read locks the reader, seeks it to
+/// the handle's position and reads, and other handles wait until that read
+/// is done, so they never see each other's positions. Cloning a handle gives
+/// a new handle at the same position.
+pub struct SharedReader<R> {
+    shared: Arc<Mutex<Shared<R>>>,
+    id: u64,
+    pos: u64,
+    // A `SeekFrom::End` seek, which needs the reader to find its end
+    end_seek: Option<i64>,
+    // Progress of the operation that holds the reader
+    inner_seek_started: bool,
+    positioned: bool,
+}
+
+struct Shared<R> {
+    reader: R,
+    next_id: u64,
+    // The handle whose operation is in flight
+    owner: Option<u64>,
+    waiting: Vec<Waker>,
+}
+
+impl<R> SharedReader<R> {
+    /// Share `reader`, starting with a handle at offset 0.
+    pub fn new(reader: R) -> Self {
+        let shared = Shared {
+            reader,
+            next_id: 1,
+            owner: None,
+            waiting: Vec::new(),
+        };
+
+        SharedReader {
+            shared: Arc::new(Mutex::new(shared)),
+            id: 0,
+            pos: 0,
+            end_seek: None,
+            inner_seek_started: false,
+            positioned: false,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared<R>> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<R> Shared<R> {
+    /// Make `id` the owner of the reader, or queue its task to be woken once
+    /// the current owner is done.
+    fn acquire(&mut self, id: u64, cx: &mut Context<'_>) -> bool {
+        match self.owner {
+            Some(owner) if owner != id => {
+                self.waiting.push(cx.waker().clone());
+                false
+            }
+            _ => {
+                self.owner = Some(id);
+                true
+            }
+        }
+    }
+
+    fn release(&mut self) {
+        self.owner = None;
+        for waker in self.waiting.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<R> Clone for SharedReader<R> {
+    fn clone(&self) -> Self {
+        let id = {
+            let mut shared = self.lock();
+            shared.next_id += 1;
+            shared.next_id - 1
+        };
+
+        SharedReader {
+            shared: Arc::clone(&self.shared),
+            id,
+            pos: self.pos,
+            end_seek: None,
+            inner_seek_started: false,
+            positioned: false,
+        }
+    }
+}
+
+impl<R> Drop for SharedReader<R> {
+    fn drop(&mut self) {
+        let mut shared = self.lock();
+        if shared.owner == Some(self.id) {
+            shared.release();
+        }
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncRead for SharedReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let shared = Arc::clone(&this.shared);
+        let mut shared = shared.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if !shared.acquire(this.id, cx) {
+            return Poll::Pending;
+        }
+
+        if !this.positioned {
+            let pos = SeekFrom::Start(this.pos);
+            match this.poll_inner_seek(&mut shared, cx, pos) {
+                Poll::Ready(Ok(_)) => this.positioned = true,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let filled = buf.filled().len();
+        let result = match Pin::new(&mut shared.reader).poll_read(cx, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        this.positioned = false;
+        shared.release();
+        if result.is_ok() {
+            this.pos += (buf.filled().len() - filled) as u64;
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncSeek for SharedReader<R> {
+    fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => {
+                Some(this.pos.checked_add_signed(delta).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative offset")
+                })?)
+            }
+            SeekFrom::End(delta) => {
+                this.end_seek = Some(delta);
+                None
+            }
+        };
+
+        if let Some(target) = target {
+            this.pos = target;
+        }
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+
+        let delta = match this.end_seek {
+            Some(delta) => delta,
+            None => return Poll::Ready(Ok(this.pos)),
+        };
+
+        let shared = Arc::clone(&this.shared);
+        let mut shared = shared.lock().unwrap_or_else(PoisonError::into_inner);
+        if !shared.acquire(this.id, cx) {
+            return Poll::Pending;
+        }
+
+        let result = match this.poll_inner_seek(&mut shared, cx, SeekFrom::End(delta)) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        this.end_seek = None;
+        shared.release();
+        if let Ok(pos) = result {
+            this.pos = pos;
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> SharedReader<R> {
+    /// Drive a seek of the shared reader while holding it. Releases the
+    /// reader if the seek fails.
+    fn poll_inner_seek(
+        &mut self,
+        shared: &mut Shared<R>,
+        cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        if !self.inner_seek_started {
+            if let Err(e) = Pin::new(&mut shared.reader).start_seek(pos) {
+                shared.release();
+                return Poll::Ready(Err(e));
+            }
+            self.inner_seek_started = true;
+        }
+
+        let result = match Pin::new(&mut shared.reader).poll_complete(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        self.inner_seek_started = false;
+        if result.is_err() {
+            shared.release();
+        }
+
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    use crate::{RevLines, RevLinesBuilder};
+    use futures_util::StreamExt;
+    use std::io::Cursor;
+    use tokio::io::BufReader;
+
+    /// Leaves every other poll pending, so tasks sharing it interleave.
+    struct Yielding {
+        inner: Cursor<Vec<u8>>,
+        ready: bool,
+    }
+
+    impl Yielding {
+        fn poll_turn(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+            self.ready = !self.ready;
+            if self.ready {
+                Poll::Ready(())
+            } else {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+
+    impl AsyncRead for Yielding {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            std::task::ready!(this.poll_turn(cx));
+            Pin::new(&mut this.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncSeek for Yielding {
+        fn start_seek(self: Pin<&mut Self>, pos: SeekFrom) -> io::Result<()> {
+            Pin::new(&mut self.get_mut().inner).start_seek(pos)
+        }
+
+        fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+            let this = self.get_mut();
+            std::task::ready!(this.poll_turn(cx));
+            Pin::new(&mut this.inner).poll_complete(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn it_reads_different_ranges_of_a_shared_reader_at_once() {
+        let data = b"ABCDEF\nGHIJK\nLMNOPQRST\nUVWXYZ\n".to_vec();
+        let reader = SharedReader::new(Yielding {
+            inner: Cursor::new(data),
+            ready: false,
+        });
+
+        // The whole reader, and the first two lines only
+        let whole = RevLines::with_capacity(4, BufReader::new(reader.clone()));
+        let first_lines = RevLinesBuilder::new()
+            .capacity(3)
+            .known_length(13)
+            .build(BufReader::new(reader));
+
+        let (whole, first_lines) = tokio::join!(
+            async {
+                whole
+                    .await
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .collect::<Vec<_>>()
+                    .await
+            },
+            async {
+                let rev_lines = first_lines.await.unwrap();
+                rev_lines.map(Result::unwrap).collect::<Vec<_>>().await
+            },
+        );
+
+        assert_eq!(whole, vec!["UVWXYZ", "LMNOPQRST", "GHIJK", "ABCDEF"]);
+        assert_eq!(first_lines, vec!["GHIJK", "ABCDEF"]);
+    }
+}